@@ -1,18 +1,154 @@
-use rand;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::distributions::Uniform;
+use rand::{Rng, SeedableRng};
 use rand_distr::Normal;
 
 const CITIES: [&str; 413] = ["Abha", "Abidjan", "Abéché", "Accra", "Addis Ababa", "Adelaide", "Aden", "Ahvaz", "Albuquerque", "Alexandra", "Alexandria", "Algiers", "Alice Springs", "Almaty", "Amsterdam", "Anadyr", "Anchorage", "Andorra la Vella", "Ankara", "Antananarivo", "Antsiranana", "Arkhangelsk", "Ashgabat", "Asmara", "Assab", "Astana", "Athens", "Atlanta", "Auckland", "Austin", "Baghdad", "Baguio", "Baku", "Baltimore", "Bamako", "Bangkok", "Bangui", "Banjul", "Barcelona", "Bata", "Batumi", "Beijing", "Beirut", "Belgrade", "Belize City", "Benghazi", "Bergen", "Berlin", "Bilbao", "Birao", "Bishkek", "Bissau", "Blantyre", "Bloemfontein", "Boise", "Bordeaux", "Bosaso", "Boston", "Bouaké", "Bratislava", "Brazzaville", "Bridgetown", "Brisbane", "Brussels", "Bucharest", "Budapest", "Bujumbura", "Bulawayo", "Burnie", "Busan", "Cabo San Lucas", "Cairns", "Cairo", "Calgary", "Canberra", "Cape Town", "Changsha", "Charlotte", "Chiang Mai", "Chicago", "Chihuahua", "Chittagong", "Chișinău", "Chongqing", "Christchurch", "City of San Marino", "Colombo", "Columbus", "Conakry", "Copenhagen", "Cotonou", "Cracow", "Da Lat", "Da Nang", "Dakar", "Dallas", "Damascus", "Dampier", "Dar es Salaam", "Darwin", "Denpasar", "Denver", "Detroit", "Dhaka", "Dikson", "Dili", "Djibouti", "Dodoma", "Dolisie", "Douala", "Dubai", "Dublin", "Dunedin", "Durban", "Dushanbe", "Edinburgh", "Edmonton", "El Paso", "Entebbe", "Erbil", "Erzurum", "Fairbanks", "Fianarantsoa", "Flores,  Petén", "Frankfurt", "Fresno", "Fukuoka", "Gaborone", "Gabès", "Gagnoa", "Gangtok", "Garissa", "Garoua", "George Town", "Ghanzi", "Gjoa Haven", "Guadalajara", "Guangzhou", "Guatemala City", "Halifax", "Hamburg", "Hamilton", "Hanga Roa", "Hanoi", "Harare", "Harbin", "Hargeisa", "Hat Yai", "Havana", "Helsinki", "Heraklion", "Hiroshima", "Ho Chi Minh City", "Hobart", "Hong Kong", "Honiara", "Honolulu", "Houston", "Ifrane", "Indianapolis", "Iqaluit", "Irkutsk", "Istanbul", "Jacksonville", "Jakarta", "Jayapura", "Jerusalem", "Johannesburg", "Jos", "Juba", "Kabul", "Kampala", "Kandi", "Kankan", "Kano", "Kansas City", "Karachi", "Karonga", "Kathmandu", "Khartoum", "Kingston", "Kinshasa", "Kolkata", "Kuala Lumpur", "Kumasi", "Kunming", "Kuopio", "Kuwait City", "Kyiv", "Kyoto", "La Ceiba", "La Paz", "Lagos", "Lahore", "Lake Havasu City", "Lake Tekapo", "Las Palmas de Gran Canaria", "Las Vegas", "Launceston", "Lhasa", "Libreville", "Lisbon", "Livingstone", "Ljubljana", "Lodwar", "Lomé", "London", "Los Angeles", "Louisville", "Luanda", "Lubumbashi", "Lusaka", "Luxembourg City", "Lviv", "Lyon", "Madrid", "Mahajanga", "Makassar", "Makurdi", "Malabo", "Malé", "Managua", "Manama", "Mandalay", "Mango", "Manila", "Maputo", "Marrakesh", "Marseille", "Maun", "Medan", "Mek'ele", "Melbourne", "Memphis", "Mexicali", "Mexico City", "Miami", "Milan", "Milwaukee", "Minneapolis", "Minsk", "Mogadishu", "Mombasa", "Monaco", "Moncton", "Monterrey", "Montreal", "Moscow", "Mumbai", "Murmansk", "Muscat", "Mzuzu", "N'Djamena", "Naha", "Nairobi", "Nakhon Ratchasima", "Napier", "Napoli", "Nashville", "Nassau", "Ndola", "New Delhi", "New Orleans", "New York City", "Ngaoundéré", "Niamey", "Nicosia", "Niigata", "Nouadhibou", "Nouakchott", "Novosibirsk", "Nuuk", "Odesa", "Odienné", "Oklahoma City", "Omaha", "Oranjestad", "Oslo", "Ottawa", "Ouagadougou", "Ouahigouya", "Ouarzazate", "Oulu", "Palembang", "Palermo", "Palm Springs", "Palmerston North", "Panama City", "Parakou", "Paris", "Perth", "Petropavlovsk-Kamchatsky", "Philadelphia", "Phnom Penh", "Phoenix", "Pittsburgh", "Podgorica", "Pointe-Noire", "Pontianak", "Port Moresby", "Port Sudan", "Port Vila", "Port-Gentil", "Portland (OR)", "Porto", "Prague", "Praia", "Pretoria", "Pyongyang", "Rabat", "Rangpur", "Reggane", "Reykjavík", "Riga", "Riyadh", "Rome", "Roseau", "Rostov-on-Don", "Sacramento", "Saint Petersburg", "Saint-Pierre", "Salt Lake City", "San Antonio", "San Diego", "San Francisco", "San Jose", "San José", "San Juan", "San Salvador", "Sana'a", "Santo Domingo", "Sapporo", "Sarajevo", "Saskatoon", "Seattle", "Seoul", "Seville", "Shanghai", "Singapore", "Skopje", "Sochi", "Sofia", "Sokoto", "Split", "St. John's", "St. Louis", "Stockholm", "Surabaya", "Suva", "Suwałki", "Sydney", "Ségou", "Tabora", "Tabriz", "Taipei", "Tallinn", "Tamale", "Tamanrasset", "Tampa", "Tashkent", "Tauranga", "Tbilisi", "Tegucigalpa", "Tehran", "Tel Aviv", "Thessaloniki", "Thiès", "Tijuana", "Timbuktu", "Tirana", "Toamasina", "Tokyo", "Toliara", "Toluca", "Toronto", "Tripoli", "Tromsø", "Tucson", "Tunis", "Ulaanbaatar", "Upington", "Vaduz", "Valencia", "Valletta", "Vancouver", "Veracruz", "Vienna", "Vientiane", "Villahermosa", "Vilnius", "Virginia Beach", "Vladivostok", "Warsaw", "Washington, D.C.", "Wau", "Wellington", "Whitehorse", "Wichita", "Willemstad", "Winnipeg", "Wrocław", "Xi'an", "Yakutsk", "Yangon", "Yaoundé", "Yellowknife", "Yerevan", "Yinchuan", "Zagreb", "Zanzibar City", "Zürich", "Ürümqi", "İzmir"];
 
+/// The canonical set of station names `gen` draws from, in a fixed (alphabetical) order. Tests may
+/// rely on this list, so it only changes deliberately.
+pub fn stations() -> &'static [&'static str] {
+    &CITIES
+}
+
 pub fn gen(n: usize) -> impl Iterator<Item=(&'static str, f64)> {
-    let mut rng = rand::thread_rng();
+    gen_with(n, &GenOptions::default())
+}
+
+/// Like `gen`, but stations come in runs: each row repeats the previous row's station with
+/// probability `1 - 1/locality`, so runs average `locality` rows. A locality of 1 (or less) picks
+/// every row's station independently, which is what `gen` does and is already as cache-unfriendly
+/// as it gets; larger values make the data friendlier to caches and branch predictors, for
+/// comparing how parsing strategies depend on that.
+pub fn gen_with_locality(n: usize, locality: f64) -> impl Iterator<Item=(&'static str, f64)> {
+    gen_with(n, &GenOptions::default().locality(locality))
+}
+
+/// Like `gen`, but the output is determined by `seed`, for reproducible test data
+pub fn gen_seeded(n: usize, seed: u64) -> impl Iterator<Item=(&'static str, f64)> {
+    gen_with(n, &GenOptions::default().seed(seed))
+}
+
+/// Settings for `gen_with`. The defaults give what `gen` does.
+#[derive(Debug, Clone)]
+pub struct GenOptions {
+    locality: f64,
+    seed: Option<u64>,
+    min_per_station: usize,
+}
+
+impl Default for GenOptions {
+    fn default() -> Self {
+        GenOptions {
+            locality: 1.0,
+            seed: None,
+            min_per_station: 0,
+        }
+    }
+}
+
+impl GenOptions {
+    /// The mean run length of a station, as for `gen_with_locality`
+    pub fn locality(mut self, locality: f64) -> Self {
+        self.locality = locality;
+        self
+    }
 
-    let city_dist = rand::distributions::Slice::new(&CITIES).unwrap();
+    /// Determine the output by `seed`, as for `gen_seeded`. Otherwise it's seeded from the OS.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Give every station at least `k` rows. Rows are still drawn at random, but once the rows
+    /// left are only just enough to make up the stations still short of `k`, each goes to one of
+    /// those. So `n` must be at least `k` times the number of `stations()`. 0 by default.
+    pub fn min_per_station(mut self, k: usize) -> Self {
+        self.min_per_station = k;
+        self
+    }
+}
+
+/// Generates `n` rows as configured by `options`. Panics if `n` is too small for
+/// `min_per_station`.
+pub fn gen_with(n: usize, options: &GenOptions) -> impl Iterator<Item=(&'static str, f64)> {
+    let rng = match options.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    generate(rng, n, options.locality, options.min_per_station)
+}
+
+fn generate<R: Rng>(
+    mut rng: R,
+    n: usize,
+    locality: f64,
+    min_per_station: usize,
+) -> impl Iterator<Item=(&'static str, f64)> {
+    // picks indexes exactly as sampling a `Slice` of the names would, so seeded output is the same
+    // with or without a minimum, until the minimum has to force a station
+    let city_dist = Uniform::new(0, CITIES.len());
     let temp_dist = Normal::new(15.0, 20.0).unwrap();
+    let stay = 1.0 - 1.0 / locality.max(1.0);
 
-    (0..n).map(move |_| {
-        let city = rng.sample(city_dist);
+    // rows still owed to each station, and the stations owed any
+    let mut owed = vec![min_per_station; CITIES.len()];
+    let mut short: Vec<usize> = if min_per_station > 0 { (0..CITIES.len()).collect() } else { Vec::new() };
+    let mut debt = min_per_station * CITIES.len();
+    assert!(n >= debt, "{n} rows can't give {} stations {min_per_station} each", CITIES.len());
+
+    let mut city = rng.sample(city_dist);
+    (0..n).map(move |i| {
+        if debt > 0 && n - i == debt {
+            city = short[rng.gen_range(0..short.len())];
+        } else if i > 0 && !(stay > 0.0 && rng.gen_bool(stay)) {
+            city = rng.sample(city_dist);
+        }
+        if owed[city] > 0 {
+            owed[city] -= 1;
+            debt -= 1;
+            if owed[city] == 0 {
+                short.retain(|&c| c != city);
+            }
+        }
         let temp = rng.sample(temp_dist);
-        (*city, temp)
+        (CITIES[city], temp)
     })
-}
\ No newline at end of file
+}
+
+/// A tiny hand-checkable input, for tests that need exact expectations rather than random rows:
+/// `gen --fixture` writes it. The aggregates, worked out by hand:
+///
+/// | station | values               | min   | mean  | max   |
+/// |---------|----------------------|-------|-------|-------|
+/// | Abha    | 10.0, 20.0, 30.0     | 10.0  | 20.0  | 30.0  |
+/// | Abéché  | 1.1, 2.2, 3.3        | 1.1   | 2.2   | 3.3   |
+/// | Cairo   | 25.5, 26.5           | 25.5  | 26.0  | 26.5  |
+/// | Oslo    | -5.0, 0.0, 5.0, -2.0 | -5.0  | -0.5  | 5.0   |
+/// | Zürich  | -12.3                | -12.3 | -12.3 | -12.3 |
+/// | İzmir   | 99.9, -99.9          | -99.9 | 0.0   | 99.9  |
+///
+/// No mean lands on a rounding tie, so the report is the same under every rounding rule that
+/// rounds to nearest. Names sort by their UTF-8 bytes, which puts `Abéché` after `Abha` and the
+/// non-ASCII `İzmir` last. See `FIXTURE_REPORT`.
+pub const FIXTURE: [(&str, f64); 15] = [
+    ("Abha", 10.0),
+    ("Oslo", -5.0),
+    ("Cairo", 25.5),
+    ("İzmir", 99.9),
+    ("Abéché", 1.1),
+    ("Abha", 20.0),
+    ("Oslo", 0.0),
+    ("Zürich", -12.3),
+    ("Abéché", 2.2),
+    ("Oslo", 5.0),
+    ("Cairo", 26.5),
+    ("İzmir", -99.9),
+    ("Abha", 30.0),
+    ("Abéché", 3.3),
+    ("Oslo", -2.0),
+];
+
+/// The report of `FIXTURE`
+pub const FIXTURE_REPORT: &str = "{Abha=10.0/20.0/30.0, Abéché=1.1/2.2/3.3, Cairo=25.5/26.0/26.5, \
+    Oslo=-5.0/-0.5/5.0, Zürich=-12.3/-12.3/-12.3, İzmir=-99.9/0.0/99.9}\n";
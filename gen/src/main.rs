@@ -1,21 +1,80 @@
 use std::env::args;
 use std::io::{BufWriter, Write};
 use std::process::exit;
-use gen;
+use gen::GenOptions;
+use onebrc::InputChecksum;
 
 fn main() {
-    let count: usize = if let [_, count_str, ..] = &args().collect::<Vec<_>>()[..] {
-        count_str.parse().expect("invalid count")
-    } else {
-        println!("Usage: gen <count>");
+    let mut count = None;
+    let mut options = GenOptions::default();
+    let mut verify_stream = false;
+    let mut min_per_station = 0;
+    let mut fixture = false;
+    let mut pad_zeros = false;
+    let mut plus = false;
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--locality" => {
+                options = options.locality(args.next().and_then(|l| l.parse().ok()).expect("invalid locality"));
+            }
+            "--seed" => options = options.seed(args.next().and_then(|s| s.parse().ok()).expect("invalid seed")),
+            "--min-per-station" => {
+                let k: usize = args.next().and_then(|k| k.parse().ok()).expect("invalid minimum per station");
+                min_per_station = k;
+                options = options.min_per_station(k);
+            }
+            "--verify-stream" => verify_stream = true,
+            "--fixture" => fixture = true,
+            "--pad-zeros" => pad_zeros = true,
+            "--plus" => plus = true,
+            _ if count.is_none() => count = Some(arg.parse::<usize>().expect("invalid count")),
+            _ => {}
+        }
+    }
+    if fixture {
+        let mut out = std::io::stdout().lock();
+        for (city, temp) in gen::FIXTURE {
+            writeln!(out, "{city};{temp:.1}").unwrap();
+        }
+        return;
+    }
+    let Some(count) = count else {
+        println!("Usage: gen [--locality <mean run length>] [--seed N] [--min-per-station K] [--verify-stream]
+           [--pad-zeros] [--plus] <count>
+       gen --fixture");
         exit(1);
     };
+    let needed = min_per_station * gen::stations().len();
+    if count < needed {
+        eprintln!("{count} rows aren't enough for {min_per_station} per station; that needs at least {needed}");
+        exit(1);
+    }
 
     let stdlock = std::io::stdout().lock();
 
     let mut bufout = BufWriter::new(stdlock);
-    gen::gen(count)
+    // with --verify-stream, checksum the rows as they go out, for onebrc --verify-input
+    let mut checksum = InputChecksum::default();
+    let mut line = Vec::new();
+    gen::gen_with(count, &options)
         .for_each(|(city, temp)| {
-            writeln!(bufout, "{city};{temp:.1}").unwrap();
-        })
+            line.clear();
+            // --pad-zeros gives at least two integer digits (05.0, -09.9), --plus a sign on positives
+            match (pad_zeros, plus) {
+                (false, false) => write!(line, "{city};{temp:.1}"),
+                (false, true) => write!(line, "{city};{temp:+.1}"),
+                (true, false) => write!(line, "{city};{temp:0width$.1}", width = if temp.is_sign_negative() { 5 } else { 4 }),
+                (true, true) => write!(line, "{city};{temp:+05.1}"),
+            }.unwrap();
+            if verify_stream {
+                checksum.add_row(&line);
+            }
+            line.push(b'\n');
+            bufout.write_all(&line).unwrap();
+        });
+    if verify_stream {
+        bufout.flush().unwrap();
+        eprintln!("{checksum}");
+    }
 }
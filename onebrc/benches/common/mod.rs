@@ -0,0 +1,32 @@
+//! Shared by the benchmarks: a fixed dataset, and a timer for parsing and aggregating it.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const SEED: u64 = 1;
+
+/// `gen::gen_seeded` rows with a fixed seed, in the standard format, so every run reads the same
+/// bytes. Generate it once, before timing anything.
+pub fn dataset(rows: usize) -> Vec<u8> {
+    gen::gen_seeded(rows, SEED)
+        .flat_map(|(city, temp)| format!("{city};{temp:.1}\n").into_bytes())
+        .collect()
+}
+
+/// The fastest of `runs` timed calls of `aggregate` on `data`, after one untimed one. Only the call
+/// is timed: the input is built beforehand, and the table is dropped after the clock stops. The
+/// input and the table both go through `black_box`, so the optimizer can neither see what's being
+/// parsed nor skip building a table that's never read.
+pub fn best(runs: usize, data: &[u8], aggregate: impl Fn(&[u8]) -> onebrc::Table) -> Duration {
+    black_box(aggregate(black_box(data)));
+    (0..runs)
+        .map(|_| {
+            let start = Instant::now();
+            let table = black_box(aggregate(black_box(data)));
+            let elapsed = start.elapsed();
+            drop(table);
+            elapsed
+        })
+        .min()
+        .expect("runs isn't zero")
+}
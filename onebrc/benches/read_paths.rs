@@ -0,0 +1,32 @@
+//! Throughput of the two ways to read input that's already in memory: `produce_table` through a
+//! `BufReader` (how files and streams are read), and `produce_table_bytes` scanning the slice in
+//! place (how a memory-mapped file would be read).
+//!
+//! ```text
+//! cargo bench -p onebrc --bench read_paths
+//! ONEBRC_BENCH_ROWS=20000000 cargo bench -p onebrc --bench read_paths
+//! ```
+//!
+//! The dataset is `gen::gen_seeded` with a fixed seed (5M rows unless `ONEBRC_BENCH_ROWS` says
+//! otherwise), so every run reads the same bytes. Each path runs a few times, after a warm-up, and
+//! the best time is reported, which is the least disturbed by whatever else the machine is doing.
+//! See `common::best` for what's timed.
+
+mod common;
+
+use std::io::BufReader;
+
+const RUNS: usize = 5;
+
+fn main() {
+    let rows = std::env::var("ONEBRC_BENCH_ROWS").ok()
+        .map_or(5_000_000, |n| n.parse().expect("ONEBRC_BENCH_ROWS should be a row count"));
+    let data = common::dataset(rows);
+    println!("{rows} rows, {:.1} MB, best of {RUNS}", data.len() as f64 / 1e6);
+
+    let buffered = common::best(RUNS, &data, |data| onebrc::produce_table(BufReader::new(data)));
+    let in_place = common::best(RUNS, &data, onebrc::produce_table_bytes);
+    for (name, time) in [("produce_table (BufReader)", buffered), ("produce_table_bytes (slice)", in_place)] {
+        println!("{name:28} {time:>10.2?}  {:7.1} MB/s", data.len() as f64 / 1e6 / time.as_secs_f64());
+    }
+}
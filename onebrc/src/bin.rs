@@ -0,0 +1,59 @@
+//! A compact binary encoding of a `Table`, used to spill partial tables to disk, and with the
+//! `binary` feature for checkpointing tables. It holds only the aggregates, not t-digests.
+
+use std::io::{self, Read, Write};
+use crate::{Sample, Table};
+
+/// Writes every station as its name's length (`u32`), the name's bytes, then `min` and `max`
+/// (`f32`), `sum` (`f64`) and `count` (`u32`). Numbers are little-endian, and stations are in no
+/// particular order.
+pub fn write_table_bin<W: Write>(table: &Table, out: &mut W) -> io::Result<()> {
+    for (name, sample) in table {
+        out.write_all(&(name.len() as u32).to_le_bytes())?;
+        out.write_all(name)?;
+        out.write_all(&sample.min.to_le_bytes())?;
+        out.write_all(&sample.max.to_le_bytes())?;
+        out.write_all(&sample.sum.to_le_bytes())?;
+        out.write_all(&sample.count.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Reads a table written by `write_table_bin`, up to the end of the input
+pub fn read_table_bin<R: Read>(input: &mut R) -> io::Result<Table> {
+    let mut table = Table::default();
+    let mut len = [0u8; 4];
+    while read_or_eof(input, &mut len)? {
+        let mut name = vec![0; u32::from_le_bytes(len) as usize];
+        input.read_exact(&mut name)?;
+        let mut fields = [0u8; 20];
+        input.read_exact(&mut fields)?;
+        let field = |at: usize| <[u8; 4]>::try_from(&fields[at..at + 4]).unwrap();
+        let sample = Sample {
+            min: f32::from_le_bytes(field(0)),
+            max: f32::from_le_bytes(field(4)),
+            sum: f64::from_le_bytes(fields[8..16].try_into().unwrap()),
+            count: u32::from_le_bytes(field(16)),
+            #[cfg(feature = "tdigest")]
+            digest: Default::default(),
+        };
+        table.entry(name).or_default().merge(&sample);
+    }
+    Ok(table)
+}
+
+/// Fills `buf`, or returns false if the input is already at its end. Running out partway through
+/// is an error.
+fn read_or_eof<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
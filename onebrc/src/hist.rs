@@ -0,0 +1,94 @@
+//! An exact histogram of one station's values, for statistics the running aggregates in `Sample`
+//! can't give, like the mode. It relies on the challenge's value format: values from -99.9 to 99.9
+//! with one decimal place, so there are only 1999 distinct values, each with its own counter
+//! (about 8KB per station, however many rows it has). Values are rounded to the nearest tenth,
+//! and ones outside the range are only counted.
+//!
+//! `HistSample` isn't part of a `Table`; fill one per station with `for_each_record`:
+//!
+//! ```
+//! use std::collections::HashMap;
+//! use std::io::BufReader;
+//! use onebrc::hist::HistSample;
+//!
+//! let input: &[u8] = b"Oslo;1.0\nOslo;2.5\nCairo;30.1\nOslo;2.5\n";
+//! let mut hists: HashMap<Vec<u8>, HistSample> = HashMap::new();
+//! onebrc::for_each_record(BufReader::new(input), &Default::default(), |name, value| {
+//!     hists.entry(name.to_vec()).or_default().add(value);
+//! }).unwrap();
+//! assert_eq!(hists[b"Oslo".as_slice()].mode(), 2.5);
+//! ```
+
+/// Tenths from -99.9 to 99.9
+const BUCKETS: usize = 1999;
+const OFFSET: i32 = 999;
+
+#[derive(Debug, Clone)]
+pub struct HistSample {
+    counts: Box<[u32; BUCKETS]>,
+    out_of_range: u64,
+}
+
+impl Default for HistSample {
+    fn default() -> Self {
+        HistSample { counts: Box::new([0; BUCKETS]), out_of_range: 0 }
+    }
+}
+
+impl HistSample {
+    pub fn add(&mut self, v: f32) {
+        let tenths = (v * 10.0).round();
+        // checked as a float, before the cast: NaN fails the comparison, and an infinite or huge
+        // value would saturate the cast and overflow the offset
+        if tenths.abs() <= OFFSET as f32 {
+            self.counts[(tenths as i32 + OFFSET) as usize] += 1;
+        } else {
+            self.out_of_range += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.counts.iter_mut().zip(other.counts.iter()).for_each(|(c, o)| *c += o);
+        self.out_of_range += other.out_of_range;
+    }
+
+    /// How many values are in the histogram, not counting those out of range
+    pub fn count(&self) -> u64 {
+        self.counts.iter().map(|&c| c as u64).sum()
+    }
+
+    /// How many values fell outside -99.9..=99.9 (or weren't finite), and so aren't in the
+    /// histogram
+    pub fn out_of_range(&self) -> u64 {
+        self.out_of_range
+    }
+
+    /// The most frequent value, the lowest of them if several are equally frequent, or NaN if the
+    /// histogram is empty (like `Sample::mean`)
+    ///
+    /// ```
+    /// use onebrc::hist::HistSample;
+    ///
+    /// let mut hist = HistSample::default();
+    /// for v in [-3.0, 12.4, 12.4, 12.4, 15.0, 15.0, 20.1, 12.4, -3.0] {
+    ///     hist.add(v);
+    /// }
+    /// assert_eq!(hist.mode(), 12.4);
+    ///
+    /// // ties go to the lower value
+    /// hist.add(15.0);
+    /// hist.add(15.0);
+    /// assert_eq!(hist.mode(), 12.4);
+    ///
+    /// assert!(HistSample::default().mode().is_nan());
+    /// ```
+    pub fn mode(&self) -> f32 {
+        let mut best: Option<(usize, u32)> = None;
+        for (i, &c) in self.counts.iter().enumerate() {
+            if c > 0 && best.is_none_or(|(_, most)| c > most) {
+                best = Some((i, c));
+            }
+        }
+        best.map_or(f32::NAN, |(i, _)| (i as i32 - OFFSET) as f32 / 10.0)
+    }
+}
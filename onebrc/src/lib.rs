@@ -1,14 +1,39 @@
-use std::io::{BufRead, BufReader, Read, Write};
-use rustc_hash::{FxBuildHasher, FxHashMap as HashMap};
-use std::error::Error;
-use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use rustc_hash::{FxBuildHasher, FxHashMap as HashMap, FxHashSet as HashSet};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::fmt;
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+mod bin;
+pub mod hist;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod sha256;
+#[cfg(feature = "binary")]
+pub use bin::{read_table_bin, write_table_bin};
+#[cfg(feature = "tdigest")]
+pub mod tdigest;
+#[cfg(feature = "zstd")]
+mod zstd;
+#[cfg(feature = "zstd")]
+pub use zstd::run_zstd;
 
 #[derive(Debug, Clone)]
 pub struct Sample {
     min: f32,
     max: f32,
-    sum: f32,
+    sum: f64,
     count: u32,
+    /// The values' distribution, for approximate quantiles. It's only in memory: tables spilled to
+    /// disk (`RunOptions::spill_dir`) come back without it.
+    #[cfg(feature = "tdigest")]
+    digest: tdigest::TDigest,
 }
 
 impl Default for Sample {
@@ -18,27 +43,62 @@ impl Default for Sample {
             max: f32::MIN,
             sum: 0.0,
             count: 0,
+            #[cfg(feature = "tdigest")]
+            digest: tdigest::TDigest::default(),
         }
     }
 }
 
 impl From<f32> for Sample {
     fn from(value: f32) -> Self {
-        Sample {
-            min: value,
-            max: value,
-            sum: value,
-            count: 1,
-        }
+        let mut sample = Sample::default();
+        sample.add(value);
+        sample
+    }
+}
+
+/// A one-value sample from a temperature's text, parsed by `parse_decimal_checked`
+///
+/// ```
+/// use onebrc::{ParseError, Sample};
+///
+/// let sample = Sample::try_from(b"-12.3".as_slice()).unwrap();
+/// assert_eq!((sample.count(), sample.min(), sample.max()), (1, -12.3, -12.3));
+/// assert_eq!(Sample::try_from(b"12,3".as_slice()).unwrap_err(), ParseError::BadCharacter(b','));
+/// assert!(Sample::try_from(b"".as_slice()).is_err());
+/// ```
+impl TryFrom<&[u8]> for Sample {
+    type Error = ParseError;
+
+    fn try_from(value: &[u8]) -> Result<Self, ParseError> {
+        parse_decimal_checked(value).map(Sample::from)
     }
 }
 
 impl Sample {
+    /// A sample with the given aggregates, e.g. from a snapshot, without replaying the values.
+    /// They're checked for consistency (`count > 0` and `min <= max`) only in debug builds.
+    /// With the `tdigest` feature, the sample has no distribution, so no quantiles.
+    pub fn new(min: f32, max: f32, sum: f64, count: u32) -> Self {
+        debug_assert!(count > 0, "a sample needs at least one value");
+        debug_assert!(min <= max, "min {min} is above max {max}");
+        Sample {
+            min,
+            max,
+            sum,
+            count,
+            #[cfg(feature = "tdigest")]
+            digest: tdigest::TDigest::default(),
+        }
+    }
+
     pub fn add(&mut self, v: f32) {
         self.min = self.min.min(v);
         self.max = self.max.max(v);
-        self.sum += v;
+        self.sum += v as f64;
         self.count += 1;
+        #[cfg(feature = "tdigest")]
+        self.digest.add(v as f64);
     }
 
     pub fn merge(&mut self, other: &Self) {
@@ -46,167 +106,3142 @@ impl Sample {
         self.max = self.max.max(other.max);
         self.sum += other.sum;
         self.count += other.count;
+        #[cfg(feature = "tdigest")]
+        self.digest.merge(&other.digest);
+    }
+
+    /// The approximate value below which a fraction `q` of the values lie (0.5 for the median),
+    /// from the sample's t-digest, or `None` if it has none. See the `tdigest` module for the
+    /// accuracy.
+    #[cfg(feature = "tdigest")]
+    pub fn quantile(&self, q: f64) -> Option<f32> {
+        self.digest.quantile(q).map(|v| v as f32)
     }
 
+    /// The mean, as reports print it. See `mean_f64` for the full precision of the sum.
     pub fn mean(&self) -> f32 {
-        self.sum / self.count as f32
+        self.mean_f64() as f32
+    }
+
+    /// The mean at full precision: the sum is kept in `f64`, so for a sample of millions of
+    /// values this is good to many more digits than `mean`'s `f32`, for analytics on the table.
+    ///
+    /// ```
+    /// use onebrc::Sample;
+    ///
+    /// let mut sample = Sample::default();
+    /// for i in 0..10_000_000 {
+    ///     sample.add(if i % 2 == 0 { 10.1 } else { 10.2 });
+    /// }
+    /// // the f32 values are a little off 10.1 and 10.2, so the exact mean is too
+    /// let exact = (10.1f32 as f64 + 10.2f32 as f64) / 2.0;
+    /// assert!((sample.mean_f64() - exact).abs() < 1e-9);
+    /// assert_eq!(sample.mean(), exact as f32);
+    /// ```
+    pub fn mean_f64(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+
+    pub fn min(&self) -> f32 {
+        self.min
+    }
+
+    pub fn max(&self) -> f32 {
+        self.max
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// How far the values spread, `max - min`; 0.0 for an empty sample, rather than the
+    /// difference of the sentinels.
+    ///
+    /// ```
+    /// use onebrc::Sample;
+    ///
+    /// let mut sample = Sample::from(-3.5);
+    /// sample.add(12.0);
+    /// assert_eq!(sample.range(), 15.5);
+    /// assert_eq!(Sample::from(7.0).range(), 0.0);
+    /// assert_eq!(Sample::default().range(), 0.0);
+    /// ```
+    pub fn range(&self) -> f32 {
+        if self.is_empty() { 0.0 } else { self.max - self.min }
+    }
+
+    /// Whether nothing has been added. An empty sample's min and max are still the sentinels from
+    /// `Default`, and its mean is NaN.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
     }
 }
 
 pub type Table = HashMap<Vec<u8>, Sample>;
 
-fn insert_or_update(table: &mut Table, k: &[u8], v: f32) {
+/// A summary table keyed by numeric station id, from `produce_table_int`
+pub type IntTable = HashMap<u32, Sample>;
+
+/// Roughly how much heap memory a table holds, in bytes. Counts the map's allocated slots (each an
+/// owned key `Vec` header plus a `Sample`, and a byte of hash-map control data), whether or not
+/// they're occupied, plus the full capacity of every key's byte buffer. Ignores allocator overhead
+/// and the map's spare slots beyond its reported capacity, so it's an underestimate, but a close
+/// one.
+pub fn table_memory_bytes(table: &Table) -> usize {
+    let slots = table.capacity() * (std::mem::size_of::<(Vec<u8>, Sample)>() + 1);
+    let keys: usize = table.keys().map(|k| k.capacity()).sum();
+    slots + keys
+}
+
+/// Folds partial tables (e.g. one per chunk of a file) into one. No partials give an empty table.
+pub fn merge_all(partials: impl IntoIterator<Item=Table>) -> Table {
+    let mut partials = partials.into_iter();
+    let mut merged = partials.next().unwrap_or_default();
+    partials.for_each(|t| merge_into(&mut merged, t));
+    merged
+}
+
+/// The ingest settings that change what a table's keys and counts mean, for checking that two
+/// tables can be merged (see `merge_tagged`): whether names were cut short by `max_name_bytes`,
+/// and which rows `every` and `sample_rate` kept. Settings that are normalized away (Fahrenheit is
+/// converted to Celsius, the separator and decimal point only affect parsing) or that only leave
+/// out malformed rows aren't part of it. Get one from `IngestOptions::tag`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableTag {
+    truncated_names: Option<usize>,
+    every: u64,
+    keep_below: u64,
+    sample_key: u64,
+}
+
+impl fmt::Display for TableTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.truncated_names {
+            Some(max) => write!(f, "names cut to {max} bytes")?,
+            None => write!(f, "whole names")?,
+        }
+        if self.every > 1 {
+            write!(f, ", one row in {}", self.every)?;
+        }
+        if self.keep_below < u64::MAX {
+            let rate = self.keep_below as f64 / 18446744073709551616.0;
+            write!(f, ", rows sampled at {rate} (key {:016x})", self.sample_key)?;
+        }
+        Ok(())
+    }
+}
+
+/// `merge_tagged` was given tables produced with different `TableTag`s
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagMismatch {
+    pub into: TableTag,
+    pub other: TableTag,
+}
+
+impl fmt::Display for TagMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "can't merge a table of {} into one of {}", self.other, self.into)
+    }
+}
+
+impl std::error::Error for TagMismatch {}
+
+/// Merges `other` into `into`, like `merge_all`, but only if they were produced with the same
+/// `TableTag`, so that tables whose names were cut differently, or that sampled different rows,
+/// aren't silently combined. On a mismatch neither table is changed.
+///
+/// ```
+/// use onebrc::{IngestOptions, LongNamePolicy, TagMismatch};
+/// use std::io::BufReader;
+///
+/// let rows = "Ouagadougou;30.1\nOslo;-2.0\n";
+/// let produce = |options: &IngestOptions| {
+///     (onebrc::produce_table_with(BufReader::new(rows.as_bytes()), options).unwrap(), options.tag())
+/// };
+/// let whole = IngestOptions::default();
+/// let cut = IngestOptions::default().max_name_bytes(4, LongNamePolicy::Truncate);
+///
+/// let (mut table, tag) = produce(&whole);
+/// let (again, again_tag) = produce(&whole);
+/// onebrc::merge_tagged(&mut table, tag, again, again_tag).unwrap();
+/// assert_eq!(table[b"Oslo".as_slice()].count(), 2);
+///
+/// let (short, short_tag) = produce(&cut);
+/// assert!(short.contains_key(b"Ouag".as_slice()));
+/// let err = onebrc::merge_tagged(&mut table, tag, short, short_tag).unwrap_err();
+/// assert_eq!(err, TagMismatch { into: whole.tag(), other: cut.tag() });
+/// assert_eq!(err.to_string(), "can't merge a table of names cut to 4 bytes into one of whole names");
+/// assert_eq!(table[b"Oslo".as_slice()].count(), 2);
+/// ```
+pub fn merge_tagged(into: &mut Table, tag: TableTag, other: Table, other_tag: TableTag) -> Result<(), TagMismatch> {
+    if tag != other_tag {
+        return Err(TagMismatch { into: tag, other: other_tag });
+    }
+    merge_into(into, other);
+    Ok(())
+}
+
+/// Splits a table into `shards` tables (at least one) by a hash of each station's name, so that
+/// every station is in exactly one shard, and a station always goes to the same shard for the same
+/// shard count, from any table. The inverse of `merge_all`.
+///
+/// ```
+/// let table = onebrc::produce_table_bytes(b"Abha;1.0\nCairo;2.0\nOslo;3.0\nAbha;4.0\nZurich;5.0\n");
+/// let shards = onebrc::shard_table(table.clone(), 3);
+/// assert_eq!(shards.len(), 3);
+/// assert_eq!(shards.iter().map(|shard| shard.len()).sum::<usize>(), table.len());
+/// let merged = onebrc::merge_all(shards);
+/// assert_eq!(onebrc::report_to_string(&merged), onebrc::report_to_string(&table));
+/// ```
+pub fn shard_table(table: Table, shards: usize) -> Vec<Table> {
+    let shards = shards.max(1);
+    let mut tables: Vec<Table> = (0..shards)
+        .map(|_| Table::with_capacity_and_hasher(table.len() / shards, FxBuildHasher))
+        .collect();
+    for (name, sample) in table {
+        let shard = FxBuildHasher.hash_one(&name) % shards as u64;
+        tables[shard as usize].insert(name, sample);
+    }
+    tables
+}
+
+/// Every row in the table aggregated together, as if all the stations were one. Empty for an empty
+/// table.
+pub fn total(table: &Table) -> Sample {
+    table.values().fold(Sample::default(), |mut total, sample| {
+        total.merge(sample);
+        total
+    })
+}
+
+/// The mean of every row in the table: all the stations' sums over all their counts, so each
+/// station counts in proportion to its rows. That's the true average of the input, unlike the
+/// average of the per-station means, which gives a station with one row as much say as one with a
+/// million. The same as `total(table).mean()`, without building the total. NaN for an empty table.
+///
+/// ```
+/// use onebrc::{Sample, Table};
+///
+/// let mut table = Table::default();
+/// table.insert(b"Busy".to_vec(), Sample::new(10.0, 10.0, 30.0, 3));
+/// table.insert(b"Quiet".to_vec(), Sample::new(30.0, 30.0, 30.0, 1));
+/// // (10 + 10 + 10 + 30) / 4, where the mean of the means would be (10 + 30) / 2
+/// assert_eq!(onebrc::global_mean(&table), 15.0);
+/// assert!(onebrc::global_mean(&Table::default()).is_nan());
+/// ```
+pub fn global_mean(table: &Table) -> f32 {
+    let (sum, count) = table.values()
+        .fold((0.0, 0u64), |(sum, count), sample| (sum + sample.sum, count + sample.count as u64));
+    (sum / count as f64) as f32
+}
+
+/// The station whose values spread the furthest (`max - min`), with that spread. Of stations with
+/// the same spread, the one whose name sorts first (by raw bytes) wins. `None` for a table with no
+/// stations that have values.
+pub fn widest_range(table: &Table) -> Option<(&[u8], f32)> {
+    table.iter()
+        .filter(|(_, sample)| !sample.is_empty())
+        .map(|(name, sample)| (name.as_slice(), sample.range()))
+        .max_by(|(a_name, a), (b_name, b)| a.total_cmp(b).then_with(|| b_name.cmp(a_name)))
+}
+
+/// The station with the lowest value, and that value. Of stations with the same min, the one whose
+/// name sorts first (by raw bytes) wins. `None` for a table with no stations that have values.
+///
+/// ```
+/// let table = onebrc::produce_table_bytes(b"Oslo;-5.0\nCairo;30.1\nBergen;-5.0\nAbha;41.0\n");
+/// assert_eq!(onebrc::coldest(&table), Some((b"Bergen".as_slice(), -5.0)));
+/// assert_eq!(onebrc::coldest(&onebrc::Table::default()), None);
+/// ```
+pub fn coldest(table: &Table) -> Option<(&[u8], f32)> {
+    table.iter()
+        .filter(|(_, sample)| !sample.is_empty())
+        .map(|(name, sample)| (name.as_slice(), sample.min))
+        .min_by(|(a_name, a), (b_name, b)| a.total_cmp(b).then_with(|| a_name.cmp(b_name)))
+}
+
+/// The station with the highest value, and that value. Of stations with the same max, the one
+/// whose name sorts first (by raw bytes) wins. `None` for a table with no stations that have
+/// values.
+///
+/// ```
+/// let table = onebrc::produce_table_bytes(b"Oslo;-5.0\nCairo;41.0\nBergen;-5.0\nAbha;41.0\n");
+/// assert_eq!(onebrc::hottest(&table), Some((b"Abha".as_slice(), 41.0)));
+/// assert_eq!(onebrc::hottest(&onebrc::Table::default()), None);
+/// ```
+pub fn hottest(table: &Table) -> Option<(&[u8], f32)> {
+    table.iter()
+        .filter(|(_, sample)| !sample.is_empty())
+        .map(|(name, sample)| (name.as_slice(), sample.max))
+        .max_by(|(a_name, a), (b_name, b)| a.total_cmp(b).then_with(|| b_name.cmp(a_name)))
+}
+
+/// The `k` stations with the most rows, most first, with their row counts. Of stations with the
+/// same count, the one whose name sorts first (by raw bytes) comes first, and is kept if only
+/// some of them fit. Keeps the best `k` so far in a heap, so it's O(n log k) rather than sorting
+/// the whole table.
+///
+/// ```
+/// let table = onebrc::produce_table_bytes(b"Oslo;1.0\nAbha;2.0\nCairo;3.0\nOslo;4.0\nZurich;5.0\nAbha;6.0\nOslo;7.0\n");
+/// assert_eq!(onebrc::top_by_count(&table, 3), [(b"Oslo".as_slice(), 3), (b"Abha", 2), (b"Cairo", 1)]);
+/// assert_eq!(onebrc::top_by_count(&table, 10).len(), 4);
+/// assert!(onebrc::top_by_count(&table, 0).is_empty());
+/// ```
+pub fn top_by_count(table: &Table, k: usize) -> Vec<(&[u8], u32)> {
+    use std::collections::BinaryHeap;
+
+    // ordered so that the heap's top, the one to drop next, is the fewest rows, then the last name
+    let mut heap = BinaryHeap::with_capacity(k.min(table.len()) + 1);
+    for (name, sample) in table.iter().filter(|(_, sample)| !sample.is_empty()) {
+        heap.push(Reverse((sample.count, Reverse(name.as_slice()))));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec().into_iter()
+        .map(|Reverse((count, Reverse(name)))| (name, count))
+        .collect()
+}
+
+/// The whole table as owned entries, sorted by name, for callers that want to keep or hand on the
+/// results without borrowing the table. The entries are sorted by the names' raw bytes, the same
+/// order as the report, and then each name is decoded once: a valid UTF-8 name is moved into its
+/// `String` without copying, and an invalid one is copied with U+FFFD in place of the bad bytes.
+///
+/// ```
+/// let table = onebrc::produce_table_bytes(b"Oslo;1.0\nAbha;2.0\nOslo;4.0\n");
+/// let entries = onebrc::into_sorted_vec(table);
+/// assert_eq!(entries.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["Abha", "Oslo"]);
+/// assert_eq!(entries[1].1.count(), 2);
+/// assert_eq!(entries[1].1.max(), 4.0);
+/// ```
+pub fn into_sorted_vec(table: Table) -> Vec<(String, Sample)> {
+    let mut entries: Vec<_> = table.into_iter().collect();
+    entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    entries.into_iter()
+        .map(|(name, sample)| {
+            let name = String::from_utf8(name)
+                .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+            (name, sample)
+        })
+        .collect()
+}
+
+/// Keeps only the stations for which `pred` is true, e.g. to drop sparse ones before reporting
+pub fn retain_stations(table: &mut Table, pred: impl Fn(&[u8], &Sample) -> bool) {
+    table.retain(|name, sample| pred(name, sample));
+}
+
+/// Rolls up hierarchical station names (like `US/California/SanJose`) to their first `depth`
+/// components, as split by `delimiter`, merging the samples of stations that share them: depth 1
+/// gives `US`, 2 gives `US/California`. Names with no more than `depth` components are kept
+/// whole, and depth 0 merges everything into one station with an empty name. The CLI's
+/// `--group-by-prefix` uses depth 1 unless given `--group-depth`.
+pub fn group_by_prefix(table: &Table, delimiter: u8, depth: usize) -> Table {
+    let mut grouped = Table::default();
+    for (name, sample) in table {
+        let prefix = match depth {
+            0 => &name[..0],
+            _ => name.iter().enumerate()
+                .filter(|(_, &b)| b == delimiter)
+                .nth(depth - 1)
+                .map_or(&name[..], |(i, _)| &name[..i]),
+        };
+        grouped.entry(prefix.to_vec()).or_default().merge(sample);
+    }
+    grouped
+}
+
+/// Merges one table's stations into another
+fn merge_into(table: &mut Table, other: Table) {
+    other.into_iter().for_each(|(k, r)| {
+        let e = table.entry(k).or_default();
+        e.merge(&r);
+    });
+}
+
+/// Records a value, returning whether that added a station to the table
+fn insert_or_update(table: &mut Table, k: &[u8], v: f32) -> bool {
     if let Some(r) = table.get_mut(k) {
         r.add(v);
+        false
     } else {
         let r = Sample::from(v);
         table.insert(Vec::from(k), r);
+        true
     }
 }
 
-/// Takes an aligned reader and produces a summary table
-pub fn produce_table<T: Read>(mut reader: BufReader<T>) -> Table {
-    let mut table = Table::with_capacity_and_hasher(1000, FxBuildHasher);
+/// Why a value couldn't be parsed as a decimal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The value had no digits
+    Empty,
+    /// The value contained something other than a leading sign, digits and a dot
+    BadCharacter(u8),
+    /// The value had too many digits to be held exactly (more than fit in an `i32`, ignoring the
+    /// dot). Valid data has at most three.
+    Overflow,
+}
 
-    let mut stash = Vec::with_capacity(100);
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "empty value"),
+            ParseError::BadCharacter(b) => write!(f, "bad decimal character {:?}", *b as char),
+            ParseError::Overflow => write!(f, "too many digits"),
+        }
+    }
+}
 
-    // Process the rows a page at a time. Page boundaries may split rows arbitrarily, so we have
-    // to deal with those cases by stashing the end of one page and then fetching the next. We
-    // assume no row is so large as to span three pages.
-    while let Ok(mut buf) = reader.fill_buf() {
-        if buf.is_empty() {
-            break;
+impl std::error::Error for ParseError {}
+
+/// Errors from producing a table
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// A row had no separator between the name and the value
+    MissingSeparator,
+    /// The input ended partway through a row
+    MissingNewline,
+    /// A row's value wasn't a decimal
+    Parse(ParseError),
+    /// A row's value was NaN or infinite (see `NonFinitePolicy`)
+    NonFinite,
+    /// A row's name was empty (see `EmptyNamePolicy`)
+    EmptyName,
+    /// A row's name was longer than `IngestOptions::max_name_bytes`
+    NameTooLong,
+    /// A row's station wasn't an id that fits in a `u32`, for `produce_table_int`
+    BadStationId,
+    /// The worker thread for this chunk (or, in `run_dir`, this worker's files, and in `run`'s
+    /// merging, this reducer) panicked, so its rows are missing from the result
+    WorkerPanicked(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "read failed: {e}"),
+            Error::MissingSeparator => write!(f, "missing separator"),
+            Error::MissingNewline => write!(f, "missing newline"),
+            Error::Parse(e) => write!(f, "{e}"),
+            Error::NonFinite => write!(f, "value isn't a finite number"),
+            Error::EmptyName => write!(f, "empty station name"),
+            Error::NameTooLong => write!(f, "station name too long"),
+            Error::BadStationId => write!(f, "station isn't a numeric id"),
+            Error::WorkerPanicked(n) => write!(f, "worker {n} panicked"),
         }
-        let mut it = buf.iter().enumerate();
-        if let Some((sep, _)) = it.find(|(_, &b)| b == b';') {
-            if let Some((end, _)) = it.find(|(_, &b)| b == b'\n') {
-                let (name, rest) = buf.split_at(sep);
-                let (val, _) = rest[1..].split_at(end - sep - 1);
+    }
+}
 
-                let v = parse_decimal(val);
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
-                //dbg!(String::from_utf8_lossy(name), v);
-                insert_or_update(&mut table, name, v);
-                reader.consume(end+1);
-            } else {
-                // didn't get to the newline
-                stash.extend_from_slice(buf);
-                let consumed = buf.len();
-                reader.consume(consumed);
-                buf = reader.fill_buf().unwrap();
-                let mut it = buf.iter().enumerate();
-                if let Some((end, _)) = it.find(|(_, &b)| b == b'\n') {
-                    stash.extend_from_slice(&buf[..end]);
-                    let (name, rest) = stash.split_at(sep);
-                    let val = &rest[1..];
-                    let v = parse_decimal(val);
-
-                    // dbg!(String::from_utf8_lossy(name), v);
-                    insert_or_update(&mut table, name, v);
-                    reader.consume(end+1);
-                } else {
-                    panic!("Missing newline");
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Error::Parse(e)
+    }
+}
+
+/// What to do with a row whose name is empty, like `;12.3`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyNamePolicy {
+    /// Aggregate it as a station named `""`, reported as `=min/mean/max` (first, since the empty
+    /// name sorts before any other)
+    #[default]
+    Accept,
+    /// Fail with `Error::EmptyName`, or with `IngestOptions::skip_bad_lines`, leave the row out
+    /// as a bad line
+    Reject,
+}
+
+/// What to do with a row whose name is longer than `IngestOptions::max_name_bytes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LongNamePolicy {
+    /// Fail with `Error::NameTooLong`, or with `IngestOptions::skip_bad_lines`, leave the row out
+    /// as a bad line
+    #[default]
+    Reject,
+    /// Cut the name down to at most the limit, backing off to the start of a UTF-8 character, and
+    /// aggregate the row under that
+    Truncate,
+}
+
+/// What to do with a value that isn't a finite number. The parser never produces one itself, but a
+/// conversion or a more permissive number format could, and `Sample` can't aggregate them
+/// meaningfully: `min` and `max` ignore NaN, but it poisons the sum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonFinitePolicy {
+    /// Fail with `Error::NonFinite`
+    #[default]
+    Reject,
+    /// Leave the row out, counting it in `IngestStats::non_finite`
+    Skip,
+}
+
+/// Counts kept while producing a table
+#[derive(Debug, Clone, Default)]
+pub struct IngestStats {
+    /// Rows left out because their value was NaN or infinite
+    pub non_finite: u64,
+    /// Malformed rows left out, with `IngestOptions::skip_bad_lines`
+    pub bad_lines: u64,
+    /// The checksum of the data rows read, if `IngestOptions::checksum` was set
+    pub checksum: InputChecksum,
+    /// Data-quality findings, if `IngestOptions::qa` was set
+    pub qa: QaStats,
+}
+
+/// Rows that look wrong for the challenge's data, found by `IngestOptions::qa`. The values are
+/// judged as written, before any Fahrenheit conversion.
+#[derive(Debug, Clone, Default)]
+pub struct QaStats {
+    /// Rows whose value is outside ±99.9
+    pub out_of_range: QaFinding,
+    /// Rows whose value has more than one decimal place
+    pub extra_decimals: QaFinding,
+    /// Rows with an empty name or value. Those with an empty value (or just a sign) are left out,
+    /// rather than failing the run; an empty name is up to `IngestOptions::empty_names`.
+    pub empty: QaFinding,
+}
+
+/// How many rows fell into a `QaStats` category, and the first few of them
+#[derive(Debug, Clone, Default)]
+pub struct QaFinding {
+    pub count: u64,
+    /// Up to `QA_EXAMPLES` of the rows, without their terminators
+    pub examples: Vec<String>,
+}
+
+/// How many example rows a `QaFinding` keeps
+pub const QA_EXAMPLES: usize = 3;
+
+impl QaFinding {
+    fn add(&mut self, row: &[u8]) {
+        self.count += 1;
+        if self.examples.len() < QA_EXAMPLES {
+            self.examples.push(String::from_utf8_lossy(row).into_owned());
+        }
+    }
+
+    /// Adds another chunk's finding, keeping the examples from this one first
+    pub fn merge(&mut self, other: &Self) {
+        self.count += other.count;
+        let room = QA_EXAMPLES.saturating_sub(self.examples.len());
+        self.examples.extend(other.examples.iter().take(room).cloned());
+    }
+}
+
+impl QaStats {
+    /// Checks a row (without its terminator) split into its name and value, and says whether to
+    /// leave it out
+    fn check(&mut self, row: &[u8], name: &[u8], val: &[u8], point: u8) -> bool {
+        let empty_value = matches!(val, [] | [b'-' | b'+']);
+        if name.is_empty() || empty_value {
+            self.empty.add(row);
+        }
+        if empty_value {
+            return true;
+        }
+        if val.iter().position(|&b| b == point).is_some_and(|dot| val.len() - dot > 2) {
+            self.extra_decimals.add(row);
+        }
+        if parse_decimal_with(val, point).is_ok_and(|v| v.abs() > 99.9) {
+            self.out_of_range.add(row);
+        }
+        false
+    }
+
+    /// Adds another chunk's findings, keeping the examples from this one first
+    pub fn merge(&mut self, other: &Self) {
+        self.out_of_range.merge(&other.out_of_range);
+        self.extra_decimals.merge(&other.extra_decimals);
+        self.empty.merge(&other.empty);
+    }
+}
+
+/// A checksum of a file's data rows, to confirm a reader saw exactly the rows that were written.
+/// Each row's bytes, without its terminator, are hashed with 64-bit FNV-1a, and the checksum is the
+/// number of rows and the wrapping sum of their hashes. Summing makes the result independent of
+/// the order rows were read in, so per-chunk checksums can be combined with `merge`. It catches
+/// lost, duplicated and altered rows, but not rows moved around the file.
+///
+/// Written (and parsed) as `<rows>-<sum in 16 hex digits>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InputChecksum {
+    rows: u64,
+    sum: u64,
+}
+
+impl InputChecksum {
+    pub fn add_row(&mut self, row: &[u8]) {
+        let hash = row.iter().fold(0xcbf29ce484222325u64, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+        self.rows += 1;
+        self.sum = self.sum.wrapping_add(hash);
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.rows += other.rows;
+        self.sum = self.sum.wrapping_add(other.sum);
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+}
+
+impl fmt::Display for InputChecksum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{:016x}", self.rows, self.sum)
+    }
+}
+
+impl std::str::FromStr for InputChecksum {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const BAD: &str = "expected a checksum like 1000-0123456789abcdef";
+        let (rows, sum) = s.split_once('-').ok_or(BAD)?;
+        Ok(InputChecksum {
+            rows: rows.parse().map_err(|_| BAD)?,
+            sum: u64::from_str_radix(sum, 16).map_err(|_| BAD)?,
+        })
+    }
+}
+
+/// Settings for `produce_table_with`. Start from the defaults, which read the standard format, and
+/// adjust with the builder methods:
+///
+/// ```
+/// let options = onebrc::IngestOptions::default().fahrenheit(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct IngestOptions {
+    fahrenheit: bool,
+    separator: u8,
+    decimal_point: u8,
+    value_first: bool,
+    terminator: u8,
+    every: u64,
+    /// With `sample_rate`, rows are kept when their hash is below this; `u64::MAX` keeps them all
+    keep_below: u64,
+    sample_key: u64,
+    start_offset: u64,
+    start_row: u64,
+    non_finite: NonFinitePolicy,
+    checksum: bool,
+    limit_rows: u64,
+    max_stations: usize,
+    skip_bad_lines: bool,
+    empty_names: EmptyNamePolicy,
+    max_name: Option<(usize, LongNamePolicy)>,
+    qa: bool,
+    scientific: bool,
+}
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        IngestOptions {
+            fahrenheit: false,
+            separator: b';',
+            decimal_point: b'.',
+            scientific: false,
+            value_first: false,
+            terminator: b'\n',
+            every: 1,
+            keep_below: u64::MAX,
+            sample_key: 0,
+            start_offset: 0,
+            start_row: 0,
+            non_finite: NonFinitePolicy::Reject,
+            checksum: false,
+            limit_rows: u64::MAX,
+            max_stations: usize::MAX,
+            skip_bad_lines: false,
+            empty_names: EmptyNamePolicy::Accept,
+            max_name: None,
+            qa: false,
+        }
+    }
+}
+
+impl IngestOptions {
+    /// The values are Fahrenheit; convert them to Celsius as they're read, so the table (and the
+    /// report) is in Celsius.
+    pub fn fahrenheit(mut self, fahrenheit: bool) -> Self {
+        self.fahrenheit = fahrenheit;
+        self
+    }
+
+    /// The byte between the name and the value. `;` by default.
+    pub fn separator(mut self, separator: u8) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// The byte between a value's integer and fractional digits. `.` by default; `,` reads
+    /// European-style values like `12,3`. It's independent of the separator, and can even be the
+    /// same byte when the name comes first, as rows are split at their first separator.
+    pub fn decimal_point(mut self, point: u8) -> Self {
+        self.decimal_point = point;
+        self
+    }
+
+    /// Also accept values in scientific notation, like `1.2e1` or `-3.0E-1`: a decimal (with the
+    /// `decimal_point`), `e` or `E`, and a whole-number exponent with an optional sign. Only values
+    /// that the usual parser rejects at the `e` are parsed again, by the standard library, so
+    /// ordinary values go the same fast way as without it, and give the same results. Off by
+    /// default.
+    pub fn scientific(mut self, scientific: bool) -> Self {
+        self.scientific = scientific;
+        self
+    }
+
+    /// Rows have the value first and the name second (`12.3;Hamburg`)
+    pub fn value_first(mut self, value_first: bool) -> Self {
+        self.value_first = value_first;
+        self
+    }
+
+    /// Configures these options from a self-describing header line (without its terminator), if
+    /// it is one. The grammar is
+    ///
+    /// ```text
+    /// # sep=<byte> order=<name,temp|temp,name>
+    /// ```
+    ///
+    /// with each setting optional (but at least one present), in any order, separated by spaces.
+    /// `sep` must be a single byte other than the line terminator. A line that doesn't match
+    /// exactly gives `None`, and is then just a (probably malformed) row.
+    ///
+    /// `produce_table_with` does this itself when the header is at the start of its input, and
+    /// skips the line. When splitting a file between workers, apply the header to the options for
+    /// every chunk, since only the first one sees it.
+    pub fn apply_header(&self, line: &[u8]) -> Option<IngestOptions> {
+        let settings = line.strip_prefix(b"# ")?;
+        let mut options = self.clone();
+        let mut any = false;
+        for setting in settings.split(|&b| b == b' ').filter(|s| !s.is_empty()) {
+            if let Some(sep) = setting.strip_prefix(b"sep=") {
+                match sep {
+                    [sep] if *sep != self.terminator => options.separator = *sep,
+                    _ => return None,
                 }
+            } else if let Some(order) = setting.strip_prefix(b"order=") {
+                options.value_first = match order {
+                    b"name,temp" => false,
+                    b"temp,name" => true,
+                    _ => return None,
+                };
+            } else {
+                return None;
             }
+            any = true;
+        }
+        any.then_some(options)
+    }
+
+    /// Rows end with a bare `\r` (classic Mac line endings) rather than `\n`. This is never
+    /// guessed from the data: a `\r`-terminated file read without it is one enormous row. It isn't
+    /// for `\r\n` endings, which aren't supported: without it they fail on the `\r` in the value,
+    /// and with it every name after the first would start with the `\n`.
+    pub fn cr_line_endings(mut self, cr: bool) -> Self {
+        self.terminator = if cr { b'\r' } else { b'\n' };
+        self
+    }
+
+    /// The byte that ends each row
+    pub fn line_terminator(&self) -> u8 {
+        self.terminator
+    }
+
+    /// Aggregate only every `n`th row, for a quick approximate look at a huge file. The table then
+    /// describes the sample: counts and means are of the sampled rows alone, and min/max can miss
+    /// extremes. A row is kept when its index in the whole input (counting lines from 0, a header
+    /// included) is a multiple of `n`, so the sample is the same however the input is split
+    /// between workers, as long as each chunk's `start_row` is set. `run` and its variants count
+    /// each chunk's rows to find out, which reads the file an extra time.
+    pub fn every(mut self, n: u64) -> Self {
+        self.every = n.max(1);
+        self
+    }
+
+    /// Aggregate each row with probability `rate` (clamped to 0..=1), decided by a hash of the
+    /// row's byte offset and `seed`: the same seed picks the same rows however the input is split,
+    /// as long as each chunk's `start_offset` is set. The table describes the sample, not the
+    /// input: counts and sums aren't scaled up by `1 / rate`. Combines with `every`, keeping only
+    /// rows both pick.
+    pub fn sample_rate(mut self, rate: f64, seed: u64) -> Self {
+        let rate = rate.clamp(0.0, 1.0);
+        // 2^64 * rate, saturating at (and so keeping everything for) a rate of 1
+        self.keep_below = (rate * 18446744073709551616.0) as u64;
+        self.sample_key = mix(seed);
+        self
+    }
+
+    /// The byte offset within the whole input at which this reader starts. Matters when sampling
+    /// with `sample_rate`, and a header is only looked for at offset 0.
+    pub fn start_offset(mut self, offset: u64) -> Self {
+        self.start_offset = offset;
+        self
+    }
+
+    /// The index within the whole input of the row this reader starts at: how many lines come
+    /// before it, a header included. Matters when sampling with `every`.
+    pub fn start_row(mut self, row: u64) -> Self {
+        self.start_row = row;
+        self
+    }
+
+    /// Stop after `n` rows (counting rows left out by sampling), for a quick look at a huge input.
+    /// `run` and `run_dir` split the cap between their chunks or files, each reading at most its
+    /// share (`n` divided between them as evenly as possible) from its start. So the cap is exact
+    /// when every part has at least its share of rows, and otherwise they read fewer than `n` in
+    /// all; it's never exceeded. Unlimited by default.
+    pub fn limit_rows(mut self, n: u64) -> Self {
+        self.limit_rows = n;
+        self
+    }
+
+    /// This reader's share of a `limit_rows` cap split between `parts` readers
+    fn limit_share(mut self, part: usize, parts: usize) -> Self {
+        let parts = parts as u64;
+        self.limit_rows = self.limit_rows / parts + u64::from((part as u64) < self.limit_rows % parts);
+        self
+    }
+
+    /// What to do with values that come out as NaN or infinite. Rejects them by default.
+    pub fn non_finite(mut self, policy: NonFinitePolicy) -> Self {
+        self.non_finite = policy;
+        self
+    }
+
+    /// Leave out malformed rows (no separator, a value that isn't a decimal, including an empty
+    /// one, or a name rejected by `empty_names` or `max_name_bytes`), counting them in
+    /// `IngestStats::bad_lines`, instead of failing on the first. An unterminated last row counts
+    /// as one too. Rows are still split at every terminator, so a bad row never affects its
+    /// neighbours. Off by default.
+    pub fn skip_bad_lines(mut self, skip: bool) -> Self {
+        self.skip_bad_lines = skip;
+        self
+    }
+
+    /// What to do with rows whose name is empty. Accepts them as a station named `""` by default.
+    pub fn empty_names(mut self, policy: EmptyNamePolicy) -> Self {
+        self.empty_names = policy;
+        self
+    }
+
+    /// Check that names are at most `max` bytes (the challenge allows 100), as a guard against
+    /// malformed data such as rows run together, and `policy` says what to do with longer ones.
+    /// Unchecked by default.
+    pub fn max_name_bytes(mut self, max: usize, policy: LongNamePolicy) -> Self {
+        self.max_name = Some((max, policy));
+        self
+    }
+
+    /// Compute an `InputChecksum` of every data row read, including rows left out by sampling, into
+    /// `IngestStats::checksum`. A header line isn't a data row. Off by default.
+    pub fn checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Check each row for data-quality problems as it's aggregated, counting them, with a few
+    /// examples, in `IngestStats::qa` (see `QaStats`) without failing the run. Off by default,
+    /// since it looks at each row twice.
+    pub fn qa(mut self, qa: bool) -> Self {
+        self.qa = qa;
+        self
+    }
+
+    /// Approximate mode for inputs with too many distinct stations to hold: keep at most `cap`
+    /// stations (besides `OTHER_STATION`), and whenever a new station would take the table past
+    /// that, fold the least frequent stations (fewest rows so far; of equal ones, the last by name)
+    /// into `OTHER_STATION`. A quarter of `cap` is evicted at a time, so the sorting out is spread
+    /// over the stations that grew the table. The runs apply the cap to each chunk's table and
+    /// then, exactly, to the merged one. Unlimited by default.
+    ///
+    /// The approximation: `OTHER_STATION` mixes the rows of every evicted station, and since a
+    /// station's count only covers its rows since it was last evicted, a station that comes back
+    /// after being evicted starts again from nothing, so its reported figures miss the rows that
+    /// went into `OTHER_STATION`. Stations common throughout the input survive with exact figures;
+    /// which rare ones survive depends on the order of the rows, and so on the chunking.
+    pub fn max_stations(mut self, cap: usize) -> Self {
+        self.max_stations = cap;
+        self
+    }
+
+    /// The settings that change what a table produced with these options means, for `merge_tagged`
+    pub fn tag(&self) -> TableTag {
+        TableTag {
+            truncated_names: match self.max_name {
+                Some((max, LongNamePolicy::Truncate)) => Some(max),
+                _ => None,
+            },
+            every: self.every,
+            keep_below: self.keep_below,
+            sample_key: if self.keep_below < u64::MAX { self.sample_key } else { 0 },
+        }
+    }
+
+    /// Whether sampling leaves out the row starting at `offset`, and numbered `index`, within this
+    /// reader
+    #[inline]
+    fn sampled_out(&self, offset: u64, index: u64) -> bool {
+        let offset = self.start_offset + offset;
+        self.every > 1 && !(self.start_row + index).is_multiple_of(self.every)
+            || self.keep_below < u64::MAX && mix(offset ^ self.sample_key) >= self.keep_below
+    }
+
+    /// Splits a row's two fields into the name and the value
+    #[inline]
+    fn name_and_value<'a>(&self, first: &'a [u8], second: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+        if self.value_first {
+            (second, first)
         } else {
-            // didn't find the separator
-            stash.extend_from_slice(buf);
-            let consumed = buf.len();
-            reader.consume(consumed);
-            buf = reader.fill_buf().unwrap();
-            let mut it = buf.iter().enumerate();
-            if let Some((sep, _)) = it.find(|(_, &b)| b == b';') {
-                if let Some((end, _)) = it.find(|(_, &b)| b == b'\n') {
-                    let (name, rest) = buf.split_at(sep);
-                    stash.extend_from_slice(name);
-                    let (val, _) = rest[1..].split_at(end - sep - 1);
-
-                    let v = parse_decimal(val);
-
-                    // dbg!(String::from_utf8_lossy(name), v);
-                    insert_or_update(&mut table, &stash, v);
-                    reader.consume(end+1);
-                } else {
-                    // didn't get to the newline
-                    stash.extend_from_slice(buf);
-                    let consumed = buf.len();
-                    reader.consume(consumed);
-                    buf = reader.fill_buf().unwrap();
-                    let mut it = buf.iter().enumerate();
-                    if let Some((end, _)) = it.find(|(_, &b)| b == b'\n') {
-                        stash.extend_from_slice(&buf[..end]);
-                        let (name, rest) = stash.split_at(sep);
-                        let val = &rest[1..];
-                        let v = parse_decimal(val);
-
-                        // dbg!(String::from_utf8_lossy(name), v);
-                        insert_or_update(&mut table, name, v);
-                        reader.consume(end+1);
-                    } else {
-                        panic!("Missing newline");
-                    }
-                }
-            }
+            (first, second)
         }
-        stash.clear();
     }
 
-    table
+    /// With `scientific`, parses a value that the usual parser failed on with `error` as scientific
+    /// notation, if it is that; otherwise, or if it isn't, the value's error is `error`
+    #[cold]
+    fn retry_scientific(&self, val: &[u8], error: ParseError) -> Result<f32, ParseError> {
+        if !self.scientific || !matches!(error, ParseError::BadCharacter(b'e' | b'E')) {
+            return Err(error);
+        }
+        let e = val.iter().position(|&b| b == b'e' || b == b'E').expect("the parser stopped at it");
+        let exponent = match &val[e + 1..] {
+            [b'-' | b'+', digits @ ..] => digits,
+            digits => digits,
+        };
+        if exponent.is_empty() || !exponent.iter().all(u8::is_ascii_digit) {
+            return Err(error);
+        }
+        parse_decimal_with(&val[..e], self.decimal_point)?;
+        let text: String = val.iter()
+            .map(|&b| if b == self.decimal_point { '.' } else { b as char })
+            .collect();
+        text.parse().map_err(|_| error)
+    }
+
+    /// Applies the unit conversion, if any, to a freshly parsed value
+    #[inline]
+    fn convert(&self, v: f32) -> f32 {
+        if self.fahrenheit {
+            fahrenheit_to_celsius(v)
+        } else {
+            v
+        }
+    }
 }
 
-/// parses the simple decimal numbers used here directly from a byte slice
-fn parse_decimal(bs: &[u8]) -> f32 {
-    let mut n = 0;
-    let mut signum = 1;
-    let mut dot = bs.len() - 1;
-    for (i, &b) in bs.iter().enumerate() {
-        match b {
-            b'-' => {
-                signum *= -1;
-            }
-            b'0'..=b'9' => {
-                let v = b - b'0';
-                n = n * 10 + signum * (v as i32)
+/// The largest index no more than `max` that doesn't split a UTF-8 character, i.e. isn't on a
+/// continuation byte (so it works on invalid UTF-8 too)
+fn utf8_floor(bytes: &[u8], max: usize) -> usize {
+    (0..=max.min(bytes.len())).rev()
+        .find(|&i| i == bytes.len() || bytes[i] & 0b1100_0000 != 0b1000_0000)
+        .unwrap_or(0)
+}
+
+/// A cheap but well-mixed hash (the splitmix64 finalizer), for picking rows deterministically
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Converts a Fahrenheit temperature to Celsius
+pub fn fahrenheit_to_celsius(f: f32) -> f32 {
+    (f - 32.0) * 5.0 / 9.0
+}
+
+/// Picks where to split the input between `n` workers so each gets about the same number of bytes.
+/// Each split point is moved forward to just past the next `eol`, so that every chunk starts at
+/// the beginning of a row. Returns the boundaries, from 0 to the input's length: chunk `i` is
+/// `bounds[i]..bounds[i + 1]`. A split point that lands in the last row (when seeking forward
+/// reaches the end of the input) or in a row already claimed by the previous split is dropped, so
+/// there may be fewer than `n` chunks, but only an empty input gives an empty one.
+pub fn chunk_boundaries<R: Read + Seek>(input: &mut R, n: usize, eol: u8) -> io::Result<Vec<u64>> {
+    let splits = explain_chunk_boundaries(input, n, eol)?;
+    let len = splits.last().map_or(0, |s| s.end);
+    Ok(splits.iter().map(|s| s.start).chain([len]).collect())
+}
+
+/// One chunk chosen by `explain_chunk_boundaries`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSplit {
+    pub start: u64,
+    pub end: u64,
+    /// How far the start was moved forward from the even split point to reach a row's start
+    pub skipped: u64,
+}
+
+/// The chunks `chunk_boundaries` picks, with how far each start had to move to align with a row.
+/// An empty input has a single empty chunk.
+pub fn explain_chunk_boundaries<R: Read + Seek>(input: &mut R, n: usize, eol: u8) -> io::Result<Vec<ChunkSplit>> {
+    let len = input.seek(SeekFrom::End(0))?;
+    let n = n.max(1) as u64;
+    let mut starts = vec![(0, 0)];
+    for pos in (1..n).map(|i| i * (len / n)) {
+        let last = starts.last().unwrap().0;
+        if pos < last {
+            continue;
+        }
+        // seek forward to align with the start of a line
+        input.seek(SeekFrom::Start(pos))?;
+        let mut b = [0u8; 1];
+        let mut boundary = pos;
+        loop {
+            if input.read(&mut b)? == 0 {
+                boundary = len;
+                break;
             }
-            b'.' => {
-                dot = i;
+            boundary += 1;
+            if b[0] == eol {
+                break;
             }
-            _ => panic!("bad decimal character {b}")
         }
-    }
-
-    let n = n as f32;
-    match (bs.len() - 1 - dot) as i32 {
-        0 => n,
-        1 => n / 10.0,
-        2 => n / 100.0,
-        3 => n / 1000.0,
-        _ => {
-            let d = (10.0f32).powi((bs.len() - 1 - dot) as i32);
-            n / d
+        if boundary > last && boundary < len {
+            starts.push((boundary, boundary - pos));
         }
     }
+    let ends = starts.iter().skip(1).map(|&(start, _)| start).chain([len]);
+    Ok(starts.iter().zip(ends).map(|(&(start, skipped), end)| ChunkSplit { start, end, skipped }).collect())
 }
 
-/// outputs the sorted report from a summary table
-pub fn report(table: &Table) -> Result<(), Box<dyn Error>> {
-    let mut stdout = std::io::stdout().lock();
-    write!(stdout, "{{")?;
+/// The chunks `run` would split the file into, for `--explain-splits`
+pub fn explain_splits<P: AsRef<Path>>(path: P, options: &RunOptions) -> io::Result<Vec<ChunkSplit>> {
+    explain_chunk_boundaries(&mut File::open(path)?, options.threads, options.ingest.terminator)
+}
 
-    let table: BTreeMap<String, &Sample> = table.iter().map(|(k, v)| {
-        let city_str = String::from_utf8_lossy(k);
-        (city_str.to_string(), v)
-    })
-        .collect();
-    let mut first = true;
-    for (city, record) in table.into_iter() {
-        if !first {
-            write!(stdout, ", ")?;
-        } else {
-            first = false;
-        }
-        write!(stdout, "{city}={:.1}/{:.1}/{:.1}", record.min, record.mean(), record.max)?;
+/// Takes an aligned reader and produces a summary table. Panics on malformed input; see
+/// `produce_table_with` for a fallible version.
+pub fn produce_table<T: Read>(reader: BufReader<T>) -> Table {
+    match produce_table_with(reader, &IngestOptions::default()) {
+        Ok(table) => table,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+/// Like `produce_table`, but starts from a table that already has an (empty) entry for each of
+/// `stations`, such as the standard dataset's list, so rows for them never allocate a key. Stations
+/// that never appear keep their empty samples, which reports leave out; drop them with
+/// `retain_stations(&mut table, |_, sample| !sample.is_empty())` where presence matters (e.g. for
+/// `diff_tables`). Rows for stations not on the list are added as usual.
+pub fn produce_table_prewarmed<T: Read>(reader: BufReader<T>, stations: &[&[u8]]) -> Table {
+    let mut table = Table::with_capacity_and_hasher(stations.len().max(1000), FxBuildHasher);
+    table.extend(stations.iter().map(|&name| (name.to_vec(), Sample::default())));
+    let scanned = scan(reader, &IngestOptions::default(), |name, v| {
+        insert_or_update(&mut table, name, v);
+        Ok(())
+    });
+    match scanned {
+        Ok(_) => table,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+/// Like `produce_table`, but aggregates only a random sample of the rows, each picked with
+/// probability `rate` by a hash of its position and `seed` (see `IngestOptions::sample_rate`), so
+/// the same seed gives the same table every time, and the same as `run` with that sample rate. The
+/// counts and sums are of the sample, not scaled up to estimate the whole input.
+pub fn produce_table_sampled<T: Read>(reader: BufReader<T>, rate: f64, seed: u64) -> Table {
+    match produce_table_with(reader, &IngestOptions::default().sample_rate(rate, seed)) {
+        Ok(table) => table,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+/// Treats several readers as one continuous stream, as if their contents were concatenated, and
+/// produces a summary table. A row may be split across the boundary between two readers.
+pub fn produce_table_chained(readers: Vec<Box<dyn Read>>) -> Table {
+    let chained = readers.into_iter()
+        .fold(Box::new(io::empty()) as Box<dyn Read>, |acc, r| Box::new(acc.chain(r)));
+    produce_table(BufReader::new(chained))
+}
+
+/// Produces a summary table from input that's already in memory, scanning it in place rather than
+/// copying it through a `BufReader`. Panics on malformed input.
+pub fn produce_table_bytes(data: &[u8]) -> Table {
+    match produce_table_spilling(data, &IngestOptions::default(), usize::MAX, |_| Ok(())) {
+        Ok((table, _)) => table,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+/// Parses rows of the standard format lazily, one `read_until` a row, as `(name, value)` pairs, for
+/// filtering or transforming them with iterator adapters before aggregating. A malformed row is an
+/// error like `produce_table_with`'s, but the rows after it are still read: it's up to the caller
+/// whether to stop. An I/O error ends the iteration.
+///
+/// Much slower than the `produce_table` family, since each row is copied out of the reader.
+///
+/// ```
+/// use onebrc::Table;
+///
+/// let rows = "Oslo;-3.0\nAbha;20.5\nOslo;1.0\nbad\nAbha;22.5\n";
+/// let mut warm = Table::default();
+/// for (name, v) in onebrc::records(rows.as_bytes()).filter_map(Result::ok).filter(|(_, v)| *v > 0.0) {
+///     warm.entry(name).or_default().add(v);
+/// }
+/// assert_eq!(onebrc::report_to_string(&warm), "{Abha=20.5/21.5/22.5, Oslo=1.0/1.0/1.0}\n");
+/// assert!(matches!(onebrc::records(rows.as_bytes()).nth(3), Some(Err(onebrc::Error::MissingSeparator))));
+/// ```
+pub fn records<R: BufRead>(mut reader: R) -> impl Iterator<Item=Result<(Vec<u8>, f32), Error>> {
+    let mut row = Vec::with_capacity(100);
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        row.clear();
+        match reader.read_until(b'\n', &mut row) {
+            Ok(0) => {
+                done = true;
+                return None;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                done = true;
+                return Some(Err(e.into()));
+            }
+        }
+        if row.pop() != Some(b'\n') {
+            return Some(Err(Error::MissingNewline));
+        }
+        let Some(sep) = row.iter().position(|&b| b == b';') else {
+            return Some(Err(Error::MissingSeparator));
+        };
+        Some(parse_decimal_checked(&row[sep + 1..])
+            .map(|v| (row[..sep].to_vec(), v))
+            .map_err(Error::Parse))
+    })
+}
+
+/// Like `produce_table_bytes`, but splits `data` into `threads` chunks at row boundaries (see
+/// `chunk_boundaries`) and aggregates them in parallel on scoped threads, merging the partial
+/// tables at the end. Panics on malformed input.
+pub fn produce_table_parallel(data: &[u8], threads: usize) -> Table {
+    let mut options = IngestOptions::default();
+    // a self-describing header has to configure every chunk, not just the first
+    let eol = options.terminator;
+    let first_line = data.split(|&b| b == eol).next().unwrap_or_default();
+    if let Some(header) = options.apply_header(first_line) {
+        options = header;
+    }
+
+    let splits = chunk_boundaries(&mut io::Cursor::new(data), threads, eol)
+        .expect("a slice can't fail to seek or read");
+    let tables = std::thread::scope(|s| {
+        let workers: Vec<_> = splits.windows(2)
+            .map(|splits| {
+                let chunk = &data[splits[0] as usize..splits[1] as usize];
+                let options = options.clone().start_offset(splits[0]);
+                s.spawn(move || produce_table_spilling(chunk, &options, usize::MAX, |_| Ok(())))
+            })
+            .collect();
+        workers.into_iter()
+            .map(|w| w.join().expect("worker panicked").map(|(table, _)| table))
+            .collect::<Result<Vec<_>, _>>()
+    });
+    match tables {
+        Ok(tables) => merge_all(tables),
+        Err(e) => panic!("{e}"),
+    }
+}
+
+/// Takes an aligned reader and produces a summary table, as configured by `options`
+pub fn produce_table_with<T: Read>(reader: BufReader<T>, options: &IngestOptions) -> Result<Table, Error> {
+    produce_table_with_stats(reader, options).map(|(table, _)| table)
+}
+
+/// Like `produce_table_with`, but also returns counts of what was skipped
+pub fn produce_table_with_stats<T: Read>(reader: BufReader<T>, options: &IngestOptions) -> Result<(Table, IngestStats), Error> {
+    produce_table_spilling(reader, options, usize::MAX, |_| Ok(()))
+}
+
+/// Like `produce_table_with`, but whenever the table reaches `limit` stations it's handed to
+/// `spill`, which is expected to save and clear it.
+fn produce_table_spilling<R: BufRead>(
+    reader: R,
+    options: &IngestOptions,
+    limit: usize,
+    mut spill: impl FnMut(&mut Table) -> Result<(), Error>,
+) -> Result<(Table, IngestStats), Error> {
+    let mut table = Table::with_capacity_and_hasher(1000, FxBuildHasher);
+    let cap = options.max_stations;
+    // one check on the rare path of a new station, for both the cap and the spill limit
+    let check_at = limit.min(cap.saturating_add(1));
+    let stats = scan(reader, options, |name, v| {
+        //dbg!(String::from_utf8_lossy(name), v);
+        if insert_or_update(&mut table, name, v) && table.len() >= check_at {
+            if table.len() > cap {
+                evict_rarest(&mut table, cap, cap - cap / 4);
+            }
+            if table.len() >= limit {
+                spill(&mut table)?;
+            }
+        }
+        Ok(())
+    })?;
+    Ok((table, stats))
+}
+
+/// Like `produce_table_with`, for data keyed by numeric station id (`42;12.3`) rather than name:
+/// each id is parsed as a `u32` (so `007` is station 7) and used as the key, so no key is ever
+/// allocated. Names aren't supported in this mode: a station that isn't all digits, or is too big
+/// for a `u32`, is an `Error::BadStationId`, even with `IngestOptions::skip_bad_lines`, which only
+/// covers the rows that `produce_table_with` would find bad. Report the table with `report_int`.
+///
+/// ```
+/// use std::io::BufReader;
+///
+/// let rows = "42;12.3\n7;-1.0\n42;10.1\n100;0.0\n";
+/// let table = onebrc::produce_table_int(BufReader::new(rows.as_bytes()), &Default::default()).unwrap();
+/// assert_eq!(table[&42].count(), 2);
+/// let mut report = Vec::new();
+/// onebrc::report_int(&table, &mut report).unwrap();
+/// assert_eq!(String::from_utf8(report).unwrap(), "{7=-1.0/-1.0/-1.0, 42=10.1/11.2/12.3, 100=0.0/0.0/0.0}\n");
+///
+/// let names = BufReader::new("Oslo;1.0\n".as_bytes());
+/// assert!(matches!(onebrc::produce_table_int(names, &Default::default()), Err(onebrc::Error::BadStationId)));
+/// ```
+pub fn produce_table_int<T: Read>(reader: BufReader<T>, options: &IngestOptions) -> Result<IntTable, Error> {
+    let mut table = IntTable::with_capacity_and_hasher(1000, FxBuildHasher);
+    scan(reader, options, |name, v| {
+        if name.is_empty() {
+            return Err(Error::BadStationId);
+        }
+        let id = name.iter().try_fold(0u32, |id, &b| match b {
+            b'0'..=b'9' => id.checked_mul(10)?.checked_add((b - b'0') as u32),
+            _ => None,
+        });
+        table.entry(id.ok_or(Error::BadStationId)?).or_default().add(v);
+        Ok(())
+    })?;
+    Ok(table)
+}
+
+/// Where `IngestOptions::max_stations` puts the rows of the stations it evicts
+pub const OTHER_STATION: &[u8] = b"__OTHER__";
+
+/// If the table has more than `cap` stations besides `OTHER_STATION`, folds the least frequent
+/// into it until `keep` are left
+#[cold]
+fn evict_rarest(table: &mut Table, cap: usize, keep: usize) {
+    let named = table.len() - usize::from(table.contains_key(OTHER_STATION));
+    if named <= cap {
+        return;
+    }
+    let mut stations: Vec<_> = table.iter()
+        .filter(|(name, _)| name.as_slice() != OTHER_STATION)
+        .map(|(name, sample)| (sample.count, Reverse(name.clone())))
+        .collect();
+    let evict = named - keep.min(named);
+    if evict < stations.len() {
+        stations.select_nth_unstable(evict);
+    }
+    let mut other = table.remove(OTHER_STATION).unwrap_or_default();
+    for (_, Reverse(name)) in &stations[..evict] {
+        other.merge(&table.remove(name).expect("the station is in the table"));
+    }
+    table.insert(OTHER_STATION.to_vec(), other);
+}
+
+/// Aggregates every row into one sample, for `RunOptions::single_bucket`
+fn produce_single_bucket<R: BufRead>(reader: R, options: &IngestOptions) -> Result<(Table, IngestStats), Error> {
+    let mut all = Sample::default();
+    let stats = scan(reader, options, |_, v| {
+        all.add(v);
+        Ok(())
+    })?;
+    let mut table = Table::default();
+    if !all.is_empty() {
+        table.insert(b"ALL".to_vec(), all);
+    }
+    Ok((table, stats))
+}
+
+/// Streams every record to `f` as its name and value, as configured by `options`, for
+/// aggregations other than a `Table` (medians, correlations, writing to another store...). It
+/// parses and splits pages exactly as `produce_table_with` does, including sampling and the header.
+/// Returns counts of what was skipped.
+///
+/// This reads one stream on the calling thread. To spread a file over threads, split it with
+/// `chunk_boundaries` and call this on each chunk from its own thread (setting each one's
+/// `IngestOptions::start_offset`); the closures are then called on those worker threads, each
+/// seeing only its chunk's records.
+///
+/// ```
+/// use std::io::BufReader;
+///
+/// let input: &[u8] = b"Hamburg;12.0\nBulawayo;8.9\nHamburg;34.2\n";
+/// let mut hamburg = Vec::new();
+/// onebrc::for_each_record(BufReader::new(input), &Default::default(), |name, value| {
+///     if name == b"Hamburg" {
+///         hamburg.push(value);
+///     }
+/// }).unwrap();
+/// assert_eq!(hamburg, [12.0, 34.2]);
+/// ```
+pub fn for_each_record<T: Read>(
+    reader: BufReader<T>,
+    options: &IngestOptions,
+    mut f: impl FnMut(&[u8], f32),
+) -> Result<IngestStats, Error> {
+    scan(reader, options, |name, v| {
+        f(name, v);
+        Ok(())
+    })
+}
+
+/// The scanner behind everything that reads rows: hands each row's name and (converted) value to
+/// `on_row`, stopping at the first error.
+fn scan<R: BufRead>(
+    mut reader: R,
+    options: &IngestOptions,
+    mut on_row: impl FnMut(&[u8], f32) -> Result<(), Error>,
+) -> Result<IngestStats, Error> {
+    let mut stash = Vec::with_capacity(100);
+    // offset of the current row's start, and its index (counting a header), for sampling
+    let mut pos = 0u64;
+    let mut index = 0u64;
+
+    let header_options;
+    let options = if options.start_offset == 0 && options.limit_rows > 0 {
+        // The first line may be longer than a page: stash pages until its terminator is in the
+        // buffer. If it isn't a header, the stash is the start of the first row, as the loop below
+        // would have left it.
+        let header = loop {
+            let buf = reader.fill_buf()?;
+            let Some(end) = buf.iter().position(|&b| b == options.terminator) else {
+                if buf.is_empty() {
+                    break None;
+                }
+                stash.extend_from_slice(buf);
+                let consumed = buf.len();
+                reader.consume(consumed);
+                continue;
+            };
+            let header = match stash.is_empty() {
+                true => options.apply_header(&buf[..end]),
+                false => options.apply_header(&[stash.as_slice(), &buf[..end]].concat()),
+            };
+            break header.map(|header| (end, header));
+        };
+        if let Some((end, header)) = header {
+            header_options = header;
+            pos = (stash.len() + end) as u64 + 1;
+            index = 1;
+            reader.consume(end + 1);
+            stash.clear();
+            &header_options
+        } else {
+            options
+        }
+    } else {
+        options
+    };
+    let sep_byte = options.separator;
+    let eol = options.terminator;
+
+    let mut stats = IngestStats::default();
+    // a row without its terminator, and where its separator is
+    let mut record = |row: &[u8], sep: Option<usize>| {
+        if let (true, Some(sep)) = (options.qa, sep) {
+            let (name, val) = options.name_and_value(&row[..sep], &row[sep + 1..]);
+            if stats.qa.check(row, name, val, options.decimal_point) {
+                return Ok(());
+            }
+        }
+        let parsed = sep.ok_or(Error::MissingSeparator).and_then(|sep| {
+            let (name, val) = options.name_and_value(&row[..sep], &row[sep + 1..]);
+            if name.is_empty() && options.empty_names == EmptyNamePolicy::Reject {
+                return Err(Error::EmptyName);
+            }
+            let name = match options.max_name {
+                Some((max, policy)) if name.len() > max => match policy {
+                    LongNamePolicy::Reject => return Err(Error::NameTooLong),
+                    LongNamePolicy::Truncate => &name[..utf8_floor(name, max)],
+                },
+                _ => name,
+            };
+            let v = parse_decimal_with(val, options.decimal_point)
+                .or_else(|e| options.retry_scientific(val, e))?;
+            Ok((name, v))
+        });
+        let (name, v) = match parsed {
+            Ok((name, v)) => (name, options.convert(v)),
+            Err(_) if options.skip_bad_lines => {
+                stats.bad_lines += 1;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        if !v.is_finite() {
+            match options.non_finite {
+                NonFinitePolicy::Reject => return Err(Error::NonFinite),
+                NonFinitePolicy::Skip => {
+                    stats.non_finite += 1;
+                    return Ok(());
+                }
+            }
+        }
+        on_row(name, v)
+    };
+
+    // Process the rows a page at a time. Page boundaries may split rows arbitrarily, so when a page
+    // ends mid-row we stash the start of the row and complete it from the following page(s). A
+    // short read (e.g. at the boundary between chained readers) just means more stashing.
+    let mut rows = 0;
+    while rows < options.limit_rows {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        if stash.is_empty() {
+            // stop at a terminator too, so a row without a separator isn't run into the next one
+            if let Some(first) = buf.iter().position(|&b| b == sep_byte || b == eol) {
+                let (sep, end) = if buf[first] == eol {
+                    (None, Some(first))
+                } else {
+                    (Some(first), buf[first + 1..].iter().position(|&b| b == eol).map(|end| first + 1 + end))
+                };
+                if let Some(end) = end {
+                    if options.checksum {
+                        stats.checksum.add_row(&buf[..end]);
+                    }
+                    if !options.sampled_out(pos, index) {
+                        record(&buf[..end], sep)?;
+                    }
+                    pos += end as u64 + 1;
+                    index += 1;
+                    rows += 1;
+                    reader.consume(end+1);
+                    continue;
+                }
+            }
+        } else if let Some(end) = buf.iter().position(|&b| b == eol) {
+            // finish off the stashed row
+            stash.extend_from_slice(&buf[..end]);
+            if options.checksum {
+                stats.checksum.add_row(&stash);
+            }
+            if !options.sampled_out(pos, index) {
+                record(&stash, stash.iter().position(|&b| b == sep_byte))?;
+            }
+            pos += stash.len() as u64 + 1;
+            index += 1;
+            rows += 1;
+            reader.consume(end+1);
+            stash.clear();
+            continue;
+        }
+
+        // didn't get to the end of the row
+        stash.extend_from_slice(buf);
+        let consumed = buf.len();
+        reader.consume(consumed);
+    }
+    if !stash.is_empty() {
+        if !options.skip_bad_lines {
+            return Err(Error::MissingNewline);
+        }
+        stats.bad_lines += 1;
+    }
+
+    Ok(stats)
+}
+
+/// Settings for `run`
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    threads: usize,
+    ingest: IngestOptions,
+    spill_dir: Option<PathBuf>,
+    spill_threshold: usize,
+    from_end: bool,
+    progress: Option<Arc<AtomicU64>>,
+    single_bucket: bool,
+    reducers: usize,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            threads: std::thread::available_parallelism().map_or(1, |n| n.into()),
+            ingest: IngestOptions::default(),
+            spill_dir: None,
+            spill_threshold: 1_000_000,
+            from_end: false,
+            progress: None,
+            single_bucket: false,
+            reducers: 1,
+        }
+    }
+}
+
+impl RunOptions {
+    /// How many chunks to split the file into, each read by its own thread. Defaults to the
+    /// available parallelism.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// How to read the rows
+    pub fn ingest(mut self, ingest: IngestOptions) -> Self {
+        self.ingest = ingest;
+        self
+    }
+
+    /// Bound the memory used by partial tables, for inputs with a huge number of distinct
+    /// stations: a worker whose table reaches the spill threshold writes it to a temporary file in
+    /// `dir` and starts afresh, and the files are merged into the result (and deleted) one at a
+    /// time. Off by default.
+    pub fn spill_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.spill_dir = Some(dir.into());
+        self
+    }
+
+    /// How many stations a worker's table may hold before it's spilled, when spilling is on.
+    /// Defaults to a million.
+    pub fn spill_threshold(mut self, stations: usize) -> Self {
+        self.spill_threshold = stations.max(1);
+        self
+    }
+
+    /// Start the workers for the last chunks first. The result is the same, but an error near the
+    /// end of a huge file turns up sooner, since only the first error is reported.
+    pub fn from_end(mut self, from_end: bool) -> Self {
+        self.from_end = from_end;
+        self
+    }
+
+    /// Add the number of bytes each worker reads to `counter` as it goes (a buffer's worth at a
+    /// time), so another thread can show progress against the file's length. `run_dir` doesn't
+    /// count.
+    pub fn progress(mut self, counter: Arc<AtomicU64>) -> Self {
+        self.progress = Some(counter);
+        self
+    }
+
+    /// Aggregate every row into one sample, under the name `ALL`, ignoring the station names: the
+    /// rows are still split and parsed, but there's no hashing or table lookup, for profiling the
+    /// rest of the work. This throws away which station each row was for, so the result is only
+    /// good for the overall min/mean/max. Spilling doesn't apply, and `run_dir` ignores it.
+    pub fn single_bucket(mut self, single_bucket: bool) -> Self {
+        self.single_bucket = single_bucket;
+        self
+    }
+
+    /// How many threads merge the workers' tables as they arrive, into tables of their own that
+    /// are merged at the end. Defaults to 1. More helps when there are many chunks with lots of
+    /// stations finishing at about the same time, so one thread can't keep up. The merges happen
+    /// in a different order, but that already depends on which worker finishes first, so the
+    /// result is the same up to the last bits of the sums. `run_dir` ignores it.
+    pub fn reducers(mut self, reducers: usize) -> Self {
+        self.reducers = reducers.max(1);
+        self
+    }
+}
+
+/// How one worker's chunk went, from `run_with_stats`
+#[derive(Debug, Clone)]
+pub struct WorkerStats {
+    /// Which chunk of the file, counting from the start
+    pub chunk: usize,
+    /// Where the chunk starts in the file
+    pub offset: u64,
+    /// The chunk's length in bytes
+    pub bytes: u64,
+    /// How many rows were aggregated
+    pub rows: u64,
+    /// How long the worker took
+    pub elapsed: Duration,
+    /// What the worker skipped
+    pub ingest: IngestStats,
+}
+
+/// What a worker hands to the reducer
+enum Partial {
+    Table(Table, WorkerStats),
+    /// A table written to this file by `bin::write_table_bin`
+    Spilled(PathBuf),
+}
+
+/// How many rows went into a table
+fn row_count(table: &Table) -> u64 {
+    table.values().map(|s| s.count as u64).sum()
+}
+
+/// Writes a worker's table to a new file in `dir` and clears it
+fn spill(table: &mut Table, dir: &Path, chunk: usize, seq: &mut usize, tx: &mpsc::Sender<Result<Partial, Error>>) -> Result<(), Error> {
+    let path = dir.join(format!("onebrc-{}-{chunk}-{seq}.bin", std::process::id()));
+    *seq += 1;
+    let mut out = io::BufWriter::new(File::create(&path)?);
+    bin::write_table_bin(table, &mut out)?;
+    out.into_inner().map_err(|e| e.into_error())?;
+    table.clear();
+    let _ = tx.send(Ok(Partial::Spilled(path)));
+    Ok(())
+}
+
+/// Produces the summary table for a whole file, splitting it into chunks that are read in
+/// parallel and merged as they finish. A file that can't seek, like a FIFO or `/dev/stdin` on a
+/// pipe, is streamed through a single worker instead.
+pub fn run<P: AsRef<Path>>(path: P, options: &RunOptions) -> Result<Table, Error> {
+    run_with_stats(path, options).map(|(table, _)| table)
+}
+
+/// Like `run`, but also says how each worker got on, in chunk order. Uneven row counts or times
+/// show the chunks (which are split by bytes, not rows) are imbalanced. The counting is done when
+/// each worker finishes, so it costs nothing per row.
+pub fn run_with_stats<P: AsRef<Path>>(path: P, options: &RunOptions) -> Result<(Table, Vec<WorkerStats>), Error> {
+    run_with_hook(path, options, |_| {})
+}
+
+/// Like `run_with_stats`, but hands each partial table to `on_partial` just before it's merged
+/// into the result: one per worker, or with spilling, each spilled table as it's read back. For
+/// logging chunk-level figures, or feeding a sketch, without changing the result.
+///
+/// The partials arrive in the order the workers finish, and `on_partial` is only ever called from
+/// the threads that merge them (see `RunOptions::reducers`), one call at a time, so it can be
+/// `FnMut` and needn't be `Sync`; it does have to be `Send`, since those threads aren't the
+/// caller's. A slow callback holds up the merging, but not the workers.
+///
+/// ```no_run
+/// let mut sizes = Vec::new();
+/// let (table, _) = onebrc::run_with_hook("measurements.txt", &Default::default(), |partial| {
+///     sizes.push(partial.len());
+/// }).unwrap();
+/// println!("{} stations, from partials of {sizes:?}", table.len());
+/// ```
+pub fn run_with_hook<P: AsRef<Path>>(
+    path: P,
+    options: &RunOptions,
+    on_partial: impl FnMut(&Table) + Send,
+) -> Result<(Table, Vec<WorkerStats>), Error> {
+    let (ingest, infiles) = open_chunks(path.as_ref(), options)?;
+    run_chunks(infiles, &ingest, options, on_partial)
+}
+
+/// Like `run_with_stats`, but reads the file `times` times over (at least once), as if it were
+/// that many copies of itself end to end: for sustained load without a huge file. Each pass is
+/// split into chunks and run like `run_with_stats`, one pass after another, and the stats are
+/// every pass's workers in turn. Counts and sums come out `times` over; the min and max are the
+/// same as for one pass, and the mean the same up to rounding. Per-chunk options like
+/// `IngestOptions::limit_rows` apply to each pass.
+pub fn run_repeated<P: AsRef<Path>>(
+    path: P,
+    options: &RunOptions,
+    times: usize,
+) -> Result<(Table, Vec<WorkerStats>), Error> {
+    let (mut table, mut stats) = run_with_stats(path.as_ref(), options)?;
+    for _ in 1..times {
+        let (pass, pass_stats) = run_with_stats(path.as_ref(), options)?;
+        merge_into(&mut table, pass);
+        stats.extend(pass_stats);
+    }
+    Ok((table, stats))
+}
+
+/// A chunk of a file to read: its offset, the index of its first row (see
+/// `IngestOptions::start_row`), its length, and a reader limited to it
+type Chunk = (u64, u64, u64, ChunkReader);
+
+/// Reads one chunk of a file that's shared by all the workers, so a run needs only one file
+/// descriptor however many threads it has. Each read says where it's reading from, with
+/// `read_at` on Unix and `seek_read` on Windows, so the workers don't share a cursor. A stream,
+/// which has no positions, is just read in order (by its only worker).
+struct ChunkReader {
+    file: Arc<File>,
+    pos: u64,
+    end: u64,
+    stream: bool,
+    progress: Option<Arc<AtomicU64>>,
+}
+
+impl ChunkReader {
+    /// How much of the chunk hasn't been read yet
+    fn remaining(&self) -> u64 {
+        self.end - self.pos
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(usize::try_from(self.remaining()).unwrap_or(usize::MAX));
+        let buf = &mut buf[..len];
+        let n = if self.stream {
+            (&*self.file).read(buf)?
+        } else if len == 0 {
+            0
+        } else {
+            read_at(&self.file, buf, self.pos)?
+        };
+        self.pos += n as u64;
+        if let Some(progress) = &self.progress {
+            progress.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    // moves the file's cursor too, but nothing else uses it
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+/// Without positioned reads, the workers take turns to seek the shared cursor and read
+#[cfg(not(any(unix, windows)))]
+fn read_at(mut file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    static CURSOR: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let _turn = CURSOR.lock().unwrap_or_else(|e| e.into_inner());
+    file.seek(SeekFrom::Start(offset))?;
+    file.read(buf)
+}
+
+/// Splits the file into chunks for `options.threads` workers, opening each one, and works out the
+/// ingest options from the header if there is one
+fn open_chunks(path: &Path, options: &RunOptions) -> Result<(IngestOptions, Vec<Chunk>), Error> {
+    let mut infile = File::open(path)?;
+    let mut ingest = options.ingest.clone();
+
+    // a FIFO or other stream can't be split, or even have its header peeked at, so it's read whole
+    // by one worker (which does see the header, at the start of its chunk)
+    if infile.stream_position().is_err() {
+        let stream = ChunkReader {
+            file: Arc::new(infile),
+            pos: 0,
+            end: u64::MAX,
+            stream: true,
+            progress: options.progress.clone(),
+        };
+        return Ok((ingest, vec![(0, 0, u64::MAX, stream)]));
+    }
+
+    // a self-describing header has to configure every chunk, not just the first
+    let mut first_line = Vec::new();
+    let eol = ingest.terminator;
+    BufReader::new(&infile).read_until(eol, &mut first_line)?;
+    let first_line = first_line.strip_suffix(&[eol]).unwrap_or(&first_line);
+    if let Some(header) = ingest.apply_header(first_line) {
+        ingest = header;
+    }
+
+    let splits = chunk_boundaries(&mut infile, options.threads, eol)?;
+    let file = Arc::new(infile);
+    let start_rows = match ingest.every > 1 {
+        true => count_rows(&file, &splits, eol)?,
+        false => vec![0; splits.len()],
+    };
+    let infiles = splits.windows(2)
+        .zip(start_rows)
+        .map(|(splits, start_row)| {
+            let reader = ChunkReader {
+                file: file.clone(),
+                pos: splits[0],
+                end: splits[1],
+                stream: false,
+                progress: options.progress.clone(),
+            };
+            (splits[0], start_row, splits[1] - splits[0], reader)
+        })
+        .collect();
+    Ok((ingest, infiles))
+}
+
+/// The index of the first row of each chunk between `splits`, for `IngestOptions::every`: the
+/// chunks before it are read through (in parallel), counting their `eol`s
+fn count_rows(file: &Arc<File>, splits: &[u64], eol: u8) -> Result<Vec<u64>, Error> {
+    let counts = std::thread::scope(|s| {
+        let counters: Vec<_> = splits.windows(2)
+            .map(|splits| {
+                let mut reader = ChunkReader {
+                    file: file.clone(),
+                    pos: splits[0],
+                    end: splits[1],
+                    stream: false,
+                    progress: None,
+                };
+                s.spawn(move || -> io::Result<u64> {
+                    let mut buf = vec![0; 2 * 1024 * 1024];
+                    let mut rows = 0;
+                    loop {
+                        let n = reader.read(&mut buf)?;
+                        if n == 0 {
+                            return Ok(rows);
+                        }
+                        rows += buf[..n].iter().filter(|&&b| b == eol).count() as u64;
+                    }
+                })
+            })
+            .collect();
+        counters.into_iter()
+            .enumerate()
+            .map(|(chunk, counter)| counter.join().map_err(|_| Error::WorkerPanicked(chunk))?.map_err(Error::from))
+            .collect::<Result<Vec<_>, Error>>()
+    })?;
+    Ok(counts.iter()
+        .scan(0, |before, rows| {
+            let start = *before;
+            *before += rows;
+            Some(start)
+        })
+        .collect())
+}
+
+/// Like `run`, but returns the result split into `shards` tables by station, as `shard_table`
+/// would split it, so each shard can be written or processed on its own. It works in two phases:
+/// each worker aggregates its chunk of the file and partitions its table by a hash of the names,
+/// then each shard's partials (one from every chunk) are merged on a thread of their own. A
+/// station's rows still come from every chunk, but they only ever meet in its shard, so the shards
+/// are merged in parallel and never need merging with each other.
+///
+/// The file is read once, as by `run`. The costs are the partitioning (a hash per station per
+/// chunk, not per row) and holding every chunk's partials until the second phase, where `run`
+/// merges them as they arrive. Spilling and `single_bucket` don't apply.
+pub fn run_partitioned<P: AsRef<Path>>(path: P, options: &RunOptions, shards: usize) -> Result<Vec<Table>, Error> {
+    let mut tables = vec![Table::default(); shards.max(1)];
+    run_partitioned_streaming(path, options, shards, |shard, table| {
+        tables[shard] = table;
+        Ok(())
+    })?;
+    Ok(tables)
+}
+
+/// Like `run_partitioned`, but hands each shard to `on_shard`, with its number, as soon as it's
+/// merged, rather than all of them at the end, so its stations can be written out while the other
+/// shards are still merging. Every station in a shard is complete by then, since a station only
+/// ever goes to one shard. The shards arrive in the order they finish, not by number. `on_shard`
+/// is called on the caller's thread; if it fails, it isn't called again, and its error is returned
+/// once the rest of the shards are merged.
+pub fn run_partitioned_streaming<P: AsRef<Path>>(
+    path: P,
+    options: &RunOptions,
+    shards: usize,
+    mut on_shard: impl FnMut(usize, Table) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let shards = shards.max(1);
+    let (ingest, infiles) = open_chunks(path.as_ref(), options)?;
+    let parts = infiles.len();
+    let partitioned = std::thread::scope(|s| {
+        let workers: Vec<_> = infiles.into_iter()
+            .enumerate()
+            .map(|(chunk, (split, start_row, _, f))| {
+                let options = ingest.clone().start_offset(split).start_row(start_row).limit_share(chunk, parts);
+                let worker = s.spawn(move || {
+                    let buf = BufReader::with_capacity(2 * 1024 * 1024, f);
+                    produce_table_with(buf, &options).map(|table| shard_table(table, shards))
+                });
+                (chunk, worker)
+            })
+            .collect();
+        workers.into_iter()
+            .map(|(chunk, worker)| worker.join().map_err(|_| Error::WorkerPanicked(chunk))?)
+            .collect::<Result<Vec<_>, Error>>()
+    })?;
+
+    // transpose, from each chunk's shards to each shard's chunks
+    let mut by_shard: Vec<Vec<Table>> = (0..shards).map(|_| Vec::with_capacity(parts)).collect();
+    for chunk in partitioned {
+        for (shard, table) in chunk.into_iter().enumerate() {
+            by_shard[shard].push(table);
+        }
+    }
+    let (tx, rx) = mpsc::channel();
+    std::thread::scope(|s| {
+        let mergers: Vec<_> = by_shard.into_iter()
+            .enumerate()
+            .map(|(shard, partials)| {
+                let tx = tx.clone();
+                s.spawn(move || {
+                    let _ = tx.send((shard, merge_all(partials)));
+                })
+            })
+            .collect();
+        drop(tx);
+        let mut result = Ok(());
+        for (shard, table) in rx {
+            if result.is_ok() {
+                result = on_shard(shard, table);
+            }
+        }
+        for (shard, merger) in mergers.into_iter().enumerate() {
+            merger.join().map_err(|_| Error::WorkerPanicked(shard))?;
+        }
+        result
+    })
+}
+
+/// Each station's population variance, from a second pass over a file whose `table` has already
+/// been produced by `run`. The second pass is split into chunks in the same way, and sums each
+/// row's deviation from its station's mean (and its square) in `f64`, which is numerically stable
+/// where a running sum of squares isn't. Using the mean only as a reference point, and correcting
+/// for the deviations' own sum, keeps it exact even though the table's mean is rounded to an `f32`.
+/// The file has to be read twice, so this won't work on a FIFO.
+pub fn run_variance<P: AsRef<Path>>(
+    path: P,
+    options: &RunOptions,
+    table: &Table,
+) -> Result<HashMap<Vec<u8>, f64>, Error> {
+    let (ingest, infiles) = open_chunks(path.as_ref(), options)?;
+    let parts = infiles.len();
+    let partials = std::thread::scope(|s| {
+        let workers: Vec<_> = infiles.into_iter()
+            .enumerate()
+            .map(|(chunk, (split, start_row, _, f))| {
+                let options = ingest.clone().start_offset(split).start_row(start_row).limit_share(chunk, parts);
+                let worker = s.spawn(move || {
+                    // per station: the sum of deviations, the sum of their squares, and the count
+                    let mut sums: HashMap<Vec<u8>, (f64, f64, u64)> = HashMap::default();
+                    let buf = BufReader::with_capacity(2 * 1024 * 1024, f);
+                    for_each_record(buf, &options, |name, v| {
+                        let d = v as f64 - table.get(name).map_or(0.0, |s| s.mean() as f64);
+                        let (sum, squares, count) = match sums.get_mut(name) {
+                            Some(sums) => sums,
+                            None => sums.entry(name.to_vec()).or_default(),
+                        };
+                        *sum += d;
+                        *squares += d * d;
+                        *count += 1;
+                    })?;
+                    Ok(sums)
+                });
+                (chunk, worker)
+            })
+            .collect();
+        workers.into_iter()
+            .map(|(chunk, worker)| worker.join().map_err(|_| Error::WorkerPanicked(chunk))?)
+            .collect::<Result<Vec<_>, Error>>()
+    })?;
+
+    let mut sums: HashMap<Vec<u8>, (f64, f64, u64)> = HashMap::default();
+    for (name, (sum, squares, count)) in partials.into_iter().flatten() {
+        let total = sums.entry(name).or_default();
+        total.0 += sum;
+        total.1 += squares;
+        total.2 += count;
+    }
+    Ok(sums.into_iter()
+        .map(|(name, (sum, squares, count))| {
+            let n = count as f64;
+            (name, (squares - sum * sum / n) / n)
+        })
+        .collect())
+}
+
+/// Aggregates each `(offset, length, reader)` chunk on its own thread, merging the results as they
+/// come in. A chunk's byte count in its stats is however much was read of it.
+fn run_chunks(
+    infiles: Vec<Chunk>,
+    ingest: &IngestOptions,
+    options: &RunOptions,
+    mut on_partial: impl FnMut(&Table) + Send,
+) -> Result<(Table, Vec<WorkerStats>), Error> {
+    let (tx, rx) = mpsc::channel::<Result<Partial, Error>>();
+    // each reducer takes whichever partial is next; after an error they all just drain the channel
+    let rx = Mutex::new(rx);
+    let on_partial = Mutex::new(&mut on_partial);
+    let failed = std::sync::atomic::AtomicBool::new(false);
+    let reduce = || {
+        let mut table = Table::default();
+        let mut stats = Vec::new();
+        let mut result = Ok(());
+        loop {
+            // in its own statement, so the lock is released before merging, not held for the body
+            let next = rx.lock().unwrap().recv();
+            let Ok(t) = next else { break };
+            if result.is_err() || failed.load(Ordering::Relaxed) {
+                // don't leave spilled partials behind after a failure
+                if let Ok(Partial::Spilled(path)) = t {
+                    let _ = std::fs::remove_file(path);
+                }
+                continue;
+            }
+            result = (|| {
+                let t = match t? {
+                    Partial::Table(t, s) => {
+                        stats.push(s);
+                        t
+                    }
+                    Partial::Spilled(path) => {
+                        let t = bin::read_table_bin(&mut BufReader::new(File::open(&path)?));
+                        std::fs::remove_file(&path)?;
+                        t?
+                    }
+                };
+                (on_partial.lock().unwrap())(&t);
+                merge_into(&mut table, t);
+                Ok(())
+            })();
+            if result.is_err() {
+                failed.store(true, Ordering::Relaxed);
+            }
+        }
+        result.map(|_| (table, stats))
+    };
+    std::thread::scope(|s| {
+        let reducers: Vec<_> = (0..options.reducers).map(|_| s.spawn(reduce)).collect();
+
+        let parts = infiles.len();
+        let mut chunks: Vec<_> = infiles.into_iter().enumerate().collect();
+        if options.from_end {
+            chunks.reverse();
+        }
+        let workers: Vec<_> = chunks.into_iter()
+            .map(|(chunk, (split, start_row, len, f))| {
+                let tx = tx.clone();
+                let spill_dir = options.spill_dir.as_deref();
+                let spill_threshold = options.spill_threshold;
+                let single_bucket = options.single_bucket;
+                let options = ingest.clone().start_offset(split).start_row(start_row).limit_share(chunk, parts);
+                let worker = s.spawn(move || {
+                    let start = Instant::now();
+                    let mut buf = BufReader::with_capacity(2 * 1024 * 1024, f);
+                    let mut spilled_rows = 0;
+                    let t = match spill_dir {
+                        _ if single_bucket => produce_single_bucket(&mut buf, &options),
+                        Some(dir) => {
+                            let mut seq = 0;
+                            produce_table_spilling(&mut buf, &options, spill_threshold, |t| {
+                                spilled_rows += row_count(t);
+                                spill(t, dir, chunk, &mut seq, &tx)
+                            })
+                        }
+                        None => produce_table_spilling(&mut buf, &options, usize::MAX, |_| Ok(())),
+                    };
+                    let t = t.map(|(t, ingest)| {
+                        let stats = WorkerStats {
+                            chunk,
+                            offset: split,
+                            bytes: len - buf.get_ref().remaining(),
+                            rows: spilled_rows + row_count(&t),
+                            elapsed: start.elapsed(),
+                            ingest,
+                        };
+                        Partial::Table(t, stats)
+                    });
+                    let _ = tx.send(t);
+                });
+                (chunk, worker)
+            })
+            .collect();
+        drop(tx);
+        let mut final_table = Table::default();
+        let mut stats = Vec::new();
+        let mut result = Ok(());
+        for (i, reducer) in reducers.into_iter().enumerate() {
+            // a reducer panics with a panic in `on_partial`, or in the others once that's
+            // poisoned the lock around it
+            match reducer.join().map_err(|_| Error::WorkerPanicked(i)).and_then(|reduced| reduced) {
+                Ok((table, s)) => {
+                    match final_table.is_empty() {
+                        true => final_table = table,
+                        false => merge_into(&mut final_table, table),
+                    }
+                    stats.extend(s);
+                }
+                Err(e) => if result.is_ok() {
+                    result = Err(e);
+                },
+            }
+        }
+        stats.sort_by_key(|s: &WorkerStats| s.chunk);
+        evict_rarest(&mut final_table, ingest.max_stations, ingest.max_stations);
+        let result = result.map(|_| (final_table, stats));
+        // a panicked worker never sends its table, so the result would silently be missing it
+        for (chunk, worker) in workers {
+            worker.join().map_err(|_| Error::WorkerPanicked(chunk))?;
+        }
+        result
+    })
+}
+
+/// Produces the summary table for a directory of shard files (`part-00000`, `part-00001`, ...),
+/// as if they were one file. Each regular file in the directory is read whole by one of the worker
+/// threads; subdirectories, dangling symlinks and other entries that aren't files are skipped. The
+/// paths are sorted and dealt out to the workers in turn, so the result doesn't depend on the order
+/// the directory lists them in.
+pub fn run_dir<P: AsRef<Path>>(dir: P, options: &RunOptions) -> Result<Table, Error> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        // following symlinks; a dangling one, or an entry removed since the listing, isn't a file
+        if std::fs::metadata(&path).is_ok_and(|m| m.is_file()) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let workers = options.threads.min(paths.len());
+    let tables = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..workers)
+            .map(|worker| {
+                let paths = &paths;
+                s.spawn(move || {
+                    let mut table = Table::default();
+                    for (i, path) in paths.iter().enumerate().skip(worker).step_by(workers) {
+                        let buf = BufReader::with_capacity(2 * 1024 * 1024, File::open(path)?);
+                        let ingest = options.ingest.clone().limit_share(i, paths.len());
+                        merge_into(&mut table, produce_table_with(buf, &ingest)?);
+                    }
+                    Ok(table)
+                })
+            })
+            .collect();
+        handles.into_iter()
+            .enumerate()
+            .map(|(worker, h)| h.join().map_err(|_| Error::WorkerPanicked(worker))?)
+            .collect::<Result<Vec<_>, Error>>()
+    })?;
+    let mut table = merge_all(tables);
+    evict_rarest(&mut table, options.ingest.max_stations, options.ingest.max_stations);
+    Ok(table)
+}
+
+/// Produces the summary table for several streams (pipes, sockets, inherited file descriptors...)
+/// as if they were separate files: each is read to its end by its own thread, and the tables are
+/// merged with `merge_all`. A stream can't be split, so there's no more parallelism than streams,
+/// and `RunOptions::threads` doesn't apply. Each stream may have its own header.
+pub fn run_streams<R: Read + Send>(streams: Vec<R>, options: &RunOptions) -> Result<Table, Error> {
+    let parts = streams.len();
+    let tables = std::thread::scope(|s| {
+        let handles: Vec<_> = streams.into_iter()
+            .enumerate()
+            .map(|(i, stream)| {
+                let ingest = options.ingest.clone().limit_share(i, parts);
+                s.spawn(move || produce_table_with(BufReader::with_capacity(2 * 1024 * 1024, stream), &ingest))
+            })
+            .collect();
+        handles.into_iter()
+            .enumerate()
+            .map(|(i, h)| h.join().map_err(|_| Error::WorkerPanicked(i))?)
+            .collect::<Result<Vec<_>, Error>>()
+    })?;
+    let mut table = merge_all(tables);
+    evict_rarest(&mut table, options.ingest.max_stations, options.ingest.max_stations);
+    Ok(table)
+}
+
+/// Parses the simple decimal numbers used in the input directly from a byte slice: an optional
+/// leading sign (`-` or `+`), then digits with at most one `.` among them. There's no exponent,
+/// and no whitespace is allowed. Panics if the bytes aren't such a number; see
+/// `parse_decimal_checked` for a fallible version.
+///
+/// ```
+/// assert_eq!(onebrc::parse_decimal(b"1.2"), 1.2);
+/// assert_eq!(onebrc::parse_decimal(b"-99.9"), -99.9);
+/// assert_eq!(onebrc::parse_decimal(b"42"), 42.0);
+/// ```
+pub fn parse_decimal(bs: &[u8]) -> f32 {
+    match parse_decimal_checked(bs) {
+        Ok(v) => v,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+/// Like `parse_decimal`, but says what's wrong with a malformed number. The digits are accumulated
+/// in an `i32`, so values with more than nine or so digits are an `Overflow` error rather than
+/// garbage.
+///
+/// Any number of decimal places is accepted, and there's no fixed scale: the digits are read as
+/// one integer and divided by the power of ten the point implies, giving an `f32` like any
+/// other. While that integer is below 2^24 (about seven significant digits) the
+/// result is exactly the `f32` nearest the text, as `str::parse` would give; past that the integer
+/// is itself rounded first, so the result can be an ulp or two off. Reports round to one place
+/// regardless, and `IngestOptions::qa` can point out rows with more.
+///
+/// ```
+/// use onebrc::{parse_decimal_checked, ParseError};
+///
+/// assert_eq!(parse_decimal_checked(b"-99.9"), Ok(-99.9));
+/// assert_eq!(parse_decimal_checked(b"+7"), Ok(7.0));
+/// assert_eq!(parse_decimal_checked(b"12.3456"), Ok(12.3456));
+/// assert_eq!(parse_decimal_checked(b"12a"), Err(ParseError::BadCharacter(b'a')));
+/// assert_eq!(parse_decimal_checked(b"-"), Err(ParseError::Empty));
+/// assert_eq!(parse_decimal_checked(b"12345678901"), Err(ParseError::Overflow));
+/// ```
+pub fn parse_decimal_checked(bs: &[u8]) -> Result<f32, ParseError> {
+    parse_decimal_with(bs, b'.')
+}
+
+/// `parse_decimal_checked` with `point` (see `IngestOptions::decimal_point`) in place of `.`
+fn parse_decimal_with(bs: &[u8], point: u8) -> Result<f32, ParseError> {
+    let (negative, bs) = match bs {
+        [b'-', rest @ ..] => (true, rest),
+        [b'+', rest @ ..] => (false, rest),
+        _ => (false, bs),
+    };
+    let mut n: i32 = 0;
+    let mut digits = false;
+    let mut dot = None;
+    for (i, &b) in bs.iter().enumerate() {
+        match b {
+            b'0'..=b'9' => {
+                let v = b - b'0';
+                n = n.checked_mul(10)
+                    .and_then(|n| n.checked_add(v as i32))
+                    .ok_or(ParseError::Overflow)?;
+                digits = true;
+            }
+            _ if b == point && dot.is_none() => {
+                dot = Some(i);
+            }
+            _ => return Err(ParseError::BadCharacter(b)),
+        }
+    }
+    if !digits {
+        return Err(ParseError::Empty);
+    }
+    if negative {
+        n = -n;
+    }
+
+    let n = n as f32;
+    let places = dot.map_or(0, |dot| bs.len() - 1 - dot);
+    Ok(match places as i32 {
+        0 => n,
+        1 => n / 10.0,
+        2 => n / 100.0,
+        3 => n / 1000.0,
+        _ => {
+            let d = (10.0f32).powi(places as i32);
+            n / d
+        }
+    })
+}
+
+/// One way in which two tables disagree, as found by `diff_tables`
+#[derive(Debug, Clone)]
+pub enum StationDiff {
+    /// The station only appears in the first table
+    OnlyInA(Vec<u8>),
+    /// The station only appears in the second table
+    OnlyInB(Vec<u8>),
+    /// The station appears in both tables, with different aggregates
+    Changed { name: Vec<u8>, a: Sample, b: Sample },
+}
+
+impl StationDiff {
+    pub fn name(&self) -> &[u8] {
+        match self {
+            StationDiff::OnlyInA(name) | StationDiff::OnlyInB(name) => name,
+            StationDiff::Changed { name, .. } => name,
+        }
+    }
+}
+
+/// The values are rounded as `report` rounds them by default (`Rounding::HalfUp`)
+impl fmt::Display for StationDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = String::from_utf8_lossy(self.name());
+        let tenths = |v: f32| Rounding::default().tenths(v as f64);
+        match self {
+            StationDiff::OnlyInA(_) => write!(f, "{name}: only in the first table"),
+            StationDiff::OnlyInB(_) => write!(f, "{name}: only in the second table"),
+            StationDiff::Changed { a, b, .. } => write!(
+                f,
+                "{name}: {:.1}/{:.1}/{:.1} ({} rows) vs {:.1}/{:.1}/{:.1} ({} rows)",
+                tenths(a.min), tenths(a.mean()), tenths(a.max), a.count,
+                tenths(b.min), tenths(b.mean()), tenths(b.max), b.count,
+            ),
+        }
+    }
+}
+
+/// How far apart min/mean/max may drift before `diff_tables` calls a station changed. Half the
+/// report's precision, so float noise from a different merge order doesn't count.
+pub const DIFF_TOLERANCE: f32 = 0.05;
+
+/// Compares two tables, station by station. Counts must match exactly; min, mean and max must be
+/// within `DIFF_TOLERANCE`. The result is sorted by station name.
+pub fn diff_tables(a: &Table, b: &Table) -> Vec<StationDiff> {
+    let differs = |l: f32, r: f32| (l - r).abs() > DIFF_TOLERANCE;
+
+    let mut diffs: Vec<StationDiff> = a.iter()
+        .filter_map(|(name, sa)| match b.get(name) {
+            None => Some(StationDiff::OnlyInA(name.clone())),
+            Some(sb) if sa.count != sb.count
+                || differs(sa.min, sb.min)
+                || differs(sa.mean(), sb.mean())
+                || differs(sa.max, sb.max) => {
+                Some(StationDiff::Changed { name: name.clone(), a: sa.clone(), b: sb.clone() })
+            }
+            Some(_) => None,
+        })
+        .chain(b.keys()
+            .filter(|name| !a.contains_key(*name))
+            .map(|name| StationDiff::OnlyInB(name.clone())))
+        .collect();
+    diffs.sort_by(|l, r| l.name().cmp(r.name()));
+    diffs
+}
+
+/// The stations in `reference` that `candidate` doesn't have at all, sorted by name (as raw
+/// bytes), e.g. to check that a reprocessed subset covers what it should. The `OnlyInA` half of
+/// `diff_tables`, without comparing the stations both have.
+///
+/// ```
+/// use onebrc::Table;
+///
+/// let reference: Table = [("Abha", 1.0), ("Cairo", 2.0), ("Oslo", 3.0)].into_iter()
+///     .map(|(name, v)| (name.as_bytes().to_vec(), v.into()))
+///     .collect();
+/// let candidate: Table = [("Cairo", 2.5), ("Zurich", 4.0)].into_iter()
+///     .map(|(name, v)| (name.as_bytes().to_vec(), v.into()))
+///     .collect();
+/// assert_eq!(onebrc::missing_stations(&reference, &candidate), [b"Abha".as_slice(), b"Oslo"]);
+/// assert!(onebrc::missing_stations(&reference, &reference).is_empty());
+/// ```
+pub fn missing_stations<'a>(reference: &'a Table, candidate: &Table) -> Vec<&'a [u8]> {
+    let mut missing: Vec<_> = reference.keys()
+        .filter(|name| !candidate.contains_key(*name))
+        .map(Vec::as_slice)
+        .collect();
+    missing.sort_unstable();
+    missing
+}
+
+/// outputs the sorted report from a summary table. Sorts references into the table rather than
+/// building a sorted copy of it, and only copies names that aren't valid UTF-8, so it needs little
+/// memory beyond the table itself.
+pub fn report<W: Write>(table: &Table, out: &mut W) -> io::Result<()> {
+    report_with(table, &ReportOptions::default(), out)
+}
+
+/// Settings for `report_with`. The defaults give the same report as `report`.
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    known: Option<HashSet<Vec<u8>>>,
+    show_missing: bool,
+    name_width: Option<usize>,
+    total_key: Option<String>,
+    mean_above: Option<f64>,
+    kelvin: bool,
+    rounding: Rounding,
+    format: ReportFormat,
+    ids: Option<HashMap<Vec<u8>, u64>>,
+    unmapped: Unmapped,
+    counts: bool,
+    utf8_errors: Utf8Errors,
+    decimal_comma: bool,
+    sort: Vec<SortBy>,
+    buffer_size: Option<usize>,
+}
+
+/// `report_with`'s default output buffer
+const REPORT_BUFFER: usize = 64 * 1024;
+
+/// What `report_with` does with station names that aren't valid UTF-8
+///
+/// ```
+/// use onebrc::{ReportOptions, Table, Utf8Errors};
+///
+/// let table: Table = [(b"Caf\xe9".to_vec(), 1.0.into()), (b"Oslo".to_vec(), 2.0.into())].into_iter().collect();
+/// let report = |policy| {
+///     let mut out = Vec::new();
+///     onebrc::report_with(&table, &ReportOptions::default().utf8_errors(policy), &mut out)
+///         .map(|()| String::from_utf8(out).unwrap())
+/// };
+/// assert_eq!(report(Utf8Errors::Replace).unwrap(), "{Caf\u{FFFD}=1.0/1.0/1.0, Oslo=2.0/2.0/2.0}\n");
+/// assert_eq!(report(Utf8Errors::Skip).unwrap(), "{Oslo=2.0/2.0/2.0}\n");
+/// assert!(report(Utf8Errors::Fail).is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Utf8Errors {
+    /// Report them with each invalid sequence replaced by `\u{FFFD}`
+    #[default]
+    Replace,
+    /// Fail with `InvalidData`, before writing anything
+    Fail,
+    /// Leave them out
+    Skip,
+}
+
+/// What `report_with` does with stations that aren't in its `ReportOptions::id_map`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Unmapped {
+    /// Report them by name, after the ones with ids
+    #[default]
+    ByName,
+    /// Leave them out
+    Skip,
+}
+
+/// How `report_with` rounds values to one decimal place
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    /// To the nearest, with ties going up (towards +∞), as the original challenge's reference
+    /// implementation does
+    #[default]
+    HalfUp,
+    /// To the nearest, with ties going to an even last digit, as Rust's `{:.1}` formatting does
+    HalfEven,
+    /// Towards zero
+    Truncate,
+    /// Towards +∞
+    Ceil,
+    /// Towards -∞
+    Floor,
+}
+
+impl Rounding {
+    /// Rounds `v` to one decimal place. Ties are values exactly halfway in binary, like 12.25; most
+    /// decimals with a 5 in the second place, like 12.35, aren't exactly representable, so they
+    /// round to whichever side they really fall. A result of zero is never negative.
+    ///
+    /// ```
+    /// use onebrc::Rounding;
+    ///
+    /// assert_eq!(Rounding::HalfUp.tenths(12.25), 12.3);
+    /// assert_eq!(Rounding::HalfUp.tenths(-12.25), -12.2);
+    /// assert_eq!(Rounding::HalfEven.tenths(12.25), 12.2);
+    /// assert_eq!(Rounding::HalfEven.tenths(12.75), 12.8);
+    /// assert_eq!(Rounding::Truncate.tenths(-12.25), -12.2);
+    /// assert_eq!(Rounding::Ceil.tenths(12.25), 12.3);
+    /// assert_eq!(Rounding::Floor.tenths(12.25), 12.2);
+    /// assert_eq!(Rounding::Floor.tenths(-12.25), -12.3);
+    /// assert_eq!(Rounding::HalfUp.tenths(-0.04).to_string(), "0");
+    /// ```
+    pub fn tenths(self, v: f64) -> f64 {
+        let x = v * 10.0;
+        let n = match self {
+            Rounding::HalfUp => {
+                let floor = x.floor();
+                if x - floor >= 0.5 { floor + 1.0 } else { floor }
+            }
+            Rounding::HalfEven => x.round_ties_even(),
+            Rounding::Truncate => x.trunc(),
+            Rounding::Ceil => x.ceil(),
+            Rounding::Floor => x.floor(),
+        };
+        // adding zero turns -0.0 into 0.0
+        n / 10.0 + 0.0
+    }
+}
+
+/// How `report_with` lays out the entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    /// `{name=min/mean/max, ...}` on one line, as `report` writes it
+    #[default]
+    Braces,
+    /// One JSON object per station per line, `{"station":"Cairo","min":..,"mean":..,"max":..}`,
+    /// with the values as numbers to one decimal place. A station without data has `null` values.
+    Ndjson,
+    /// An aligned table for reading in a terminal: a header, then a line per station with the
+    /// names left-aligned and padded to the longest, and the numbers right-aligned in columns.
+    Pretty,
+    /// With the `parquet` feature, an Apache Parquet file with a row per entry: the station, its
+    /// min, mean and max as printed (null for a station without data), and its row count. See
+    /// the schema in `src/parquet.rs`.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// What a report's entries are sorted by, for `ReportOptions::sort_by`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Min,
+    Mean,
+    Max,
+    Count,
+}
+
+/// One level of a report's sort: a key, and whether it's in descending order. Parsed from
+/// `key[:dir]`, where the key is `name`, `min`, `mean`, `max` or `count`, and the direction is
+/// `asc` (the default) or `desc`, as the CLI's `--sort` takes them in a comma-separated list, most
+/// significant first (`mean:desc,name`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortBy {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl std::str::FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, dir) = s.split_once(':').unwrap_or((s, "asc"));
+        let key = match key {
+            "name" => SortKey::Name,
+            "min" => SortKey::Min,
+            "mean" => SortKey::Mean,
+            "max" => SortKey::Max,
+            "count" => SortKey::Count,
+            _ => return Err(format!("{key:?} isn't a sort key (name, min, mean, max or count)")),
+        };
+        let descending = match dir {
+            "asc" => false,
+            "desc" => true,
+            _ => return Err(format!("{dir:?} isn't a sort direction (asc or desc)")),
+        };
+        Ok(SortBy { key, descending })
+    }
+}
+
+impl SortBy {
+    fn compare(self, (l_name, l): (&str, Option<&Sample>), (r_name, r): (&str, Option<&Sample>)) -> std::cmp::Ordering {
+        let ord = match (self.key, l, r) {
+            (SortKey::Name, _, _) => l_name.cmp(r_name),
+            (SortKey::Min, Some(l), Some(r)) => l.min.total_cmp(&r.min),
+            (SortKey::Mean, Some(l), Some(r)) => l.mean().total_cmp(&r.mean()),
+            (SortKey::Max, Some(l), Some(r)) => l.max.total_cmp(&r.max),
+            (SortKey::Count, Some(l), Some(r)) => l.count.cmp(&r.count),
+            // stations without data go last either way
+            (_, l, r) => return l.is_none().cmp(&r.is_none()),
+        };
+        if self.descending { ord.reverse() } else { ord }
+    }
+}
+
+impl ReportOptions {
+    /// Only report these stations (e.g. from a master registry, read with `read_station_list`).
+    /// Names are compared as raw bytes. Stations in the table but not in the registry are left
+    /// out.
+    pub fn known_stations(mut self, stations: impl IntoIterator<Item=Vec<u8>>) -> Self {
+        self.known = Some(stations.into_iter().collect());
+        self
+    }
+
+    /// With `known_stations`, also list the registry stations that have no data, as
+    /// `name=MISSING`, in their places in the sorted order. Otherwise they're left out like any
+    /// other station without rows.
+    pub fn show_missing(mut self, show: bool) -> Self {
+        self.show_missing = show;
+        self
+    }
+
+    /// Cut displayed names longer than `width` bytes down to at most that many, backing off to a
+    /// character boundary, and mark the cut with `…`. Stations are still sorted and aggregated by
+    /// their full names.
+    pub fn name_width(mut self, width: usize) -> Self {
+        self.name_width = Some(width);
+        self
+    }
+
+    /// Only report stations whose mean is above `threshold`, comparing the mean as it's printed
+    /// (to one decimal place) so the filter agrees with the output: with a threshold of 20.0, a
+    /// mean of 20.04 is printed as 20.0 and left out. Registry stations without data are left out
+    /// too. The threshold is an `f64`, like the rounded values it's compared with, so that a
+    /// threshold of 20.3 is the same number as a printed 20.3 (an `f32` 20.3 is a little below it).
+    pub fn mean_above(mut self, threshold: f64) -> Self {
+        self.mean_above = Some(threshold);
+        self
+    }
+
+    /// Print temperatures in Kelvin instead of Celsius. The table is still in Celsius: 273.15 is
+    /// added to each unrounded value (in `f64`, so the addition itself doesn't lose precision)
+    /// just before it's rounded to one decimal place for printing. Since 273.15 has two decimal
+    /// places, that isn't always the printed Celsius value plus 273.15 rounded. A `mean_above`
+    /// threshold is then in Kelvin too.
+    pub fn kelvin(mut self, kelvin: bool) -> Self {
+        self.kelvin = kelvin;
+        self
+    }
+
+    /// How to round values for printing (and for `mean_above`). `Rounding::HalfUp` by default.
+    pub fn rounding(mut self, rounding: Rounding) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    /// How to lay out the report. `ReportFormat::Braces` by default.
+    pub fn format(mut self, format: ReportFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sort the entries by these keys, most significant first, instead of by name. The sort is
+    /// stable over the usual order (by name, or with `id_map`, by id), so that's the last tie
+    /// break. Numeric keys compare the unrounded values, and stations without data (see
+    /// `show_missing`) come after the rest. A `with_total` entry stays last.
+    ///
+    /// ```
+    /// use onebrc::{ReportOptions, SortBy};
+    ///
+    /// let table = onebrc::produce_table_bytes(b"Abha;10.0\nCairo;30.0\nOslo;10.0\nBergen;30.0\nOslo;10.0\n");
+    /// let sort: Vec<SortBy> = "mean:desc,count:desc".split(',').map(|s| s.parse().unwrap()).collect();
+    /// let mut out = Vec::new();
+    /// onebrc::report_with(&table, &ReportOptions::default().sort_by(sort), &mut out).unwrap();
+    /// assert_eq!(
+    ///     String::from_utf8(out).unwrap(),
+    ///     "{Bergen=30.0/30.0/30.0, Cairo=30.0/30.0/30.0, Oslo=10.0/10.0/10.0, Abha=10.0/10.0/10.0}\n",
+    /// );
+    /// assert!("mean:down".parse::<SortBy>().is_err());
+    /// ```
+    pub fn sort_by(mut self, keys: Vec<SortBy>) -> Self {
+        self.sort = keys;
+        self
+    }
+
+    /// Report stations by numeric id instead of by name, as `id=min/mean/max`, in ascending order
+    /// of id. Names are looked up as raw bytes, before any `name_width` cut. Stations that aren't
+    /// in the map follow the mapped ones, by name and in the usual order, or are left out,
+    /// depending on `unmapped`. Registry stations without data are `id=MISSING` with
+    /// `show_missing`, and a `with_total` entry keeps its key.
+    pub fn id_map(mut self, ids: HashMap<Vec<u8>, u64>, unmapped: Unmapped) -> Self {
+        self.ids = Some(ids);
+        self.unmapped = unmapped;
+        self
+    }
+
+    /// Also write each station's row count and sum, as `name=min/mean/max/count/sum` (or `count`
+    /// and `sum` fields in NDJSON), so that reports from separate runs can be combined with
+    /// `read_counted_report`. A mean alone can't be: the merged mean needs each part's weight,
+    /// and its unrounded total. The sum is in the report's units, so in Kelvin it includes 273.15
+    /// per row.
+    pub fn counts(mut self, counts: bool) -> Self {
+        self.counts = counts;
+        self
+    }
+
+    /// What to do with station names that aren't valid UTF-8. `Utf8Errors::Replace` by default.
+    /// Only the stations that would be reported are checked, so a bad name that `known_stations`
+    /// or `mean_above` leaves out doesn't matter.
+    pub fn utf8_errors(mut self, policy: Utf8Errors) -> Self {
+        self.utf8_errors = policy;
+        self
+    }
+
+    /// How many bytes of the report to gather before each write to the output, so that a report
+    /// of millions of stations isn't millions of small writes. 64KB by default; 0 writes each
+    /// piece as it's formatted. The buffer is flushed before `report_with` returns, and the bytes
+    /// written are the same either way.
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.buffer_size = Some(bytes);
+        self
+    }
+
+    /// Print values with a decimal comma (`12,3`), as German and many other locales write them,
+    /// whatever `IngestOptions::decimal_point` the input had. Only the braces and pretty formats
+    /// change: JSON numbers always have a `.`. A report written like this can't be read back by
+    /// `check_report` or `read_counted_report`.
+    ///
+    /// ```
+    /// use onebrc::{ReportOptions, Table};
+    ///
+    /// let table = onebrc::produce_table_bytes(b"Berlin;12.3\nBerlin;-4.5\nM\xc3\xbcnchen;0.0\n");
+    /// let mut out = Vec::new();
+    /// onebrc::report_with(&table, &ReportOptions::default().decimal_comma(true), &mut out).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "{Berlin=-4,5/3,9/12,3, München=0,0/0,0/0,0}\n");
+    /// ```
+    pub fn decimal_comma(mut self, decimal_comma: bool) -> Self {
+        self.decimal_comma = decimal_comma;
+        self
+    }
+
+    /// Append an entry named `key` (the CLI uses `__ALL__`) with the `total` of every row in the
+    /// table, including stations that `known_stations` leaves out. It always comes last, whatever
+    /// its name, so pick one that can't be confused with a real station.
+    pub fn with_total(mut self, key: impl Into<String>) -> Self {
+        self.total_key = Some(key.into());
+        self
+    }
+}
+
+/// Reads a newline-separated list of station names, such as a registry for
+/// `ReportOptions::known_stations`. Names are kept as raw bytes; blank lines are skipped.
+pub fn read_station_list<R: BufRead>(reader: R) -> io::Result<Vec<Vec<u8>>> {
+    reader.split(b'\n')
+        .filter(|name| !matches!(name, Ok(name) if name.is_empty()))
+        .collect()
+}
+
+/// Reads a station-to-id map for `ReportOptions::id_map`: one `name;id` line per station, split at
+/// the last `;`, with blank lines skipped. A line without an id, or an id that isn't a
+/// non-negative integer, is `InvalidData`, and so is an id given to two different stations, since
+/// the report couldn't tell them apart. A name listed twice takes its last id.
+pub fn read_id_map<R: BufRead>(reader: R) -> io::Result<HashMap<Vec<u8>, u64>> {
+    let invalid = |line: usize, why: &str| io::Error::new(io::ErrorKind::InvalidData, format!("line {line}: {why}"));
+    let mut ids = HashMap::default();
+    for (i, line) in reader.split(b'\n').enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let split = line.iter().rposition(|&b| b == b';').ok_or_else(|| invalid(i + 1, "no ';' before the id"))?;
+        let id = std::str::from_utf8(&line[split + 1..]).ok()
+            .and_then(|id| id.trim_end_matches('\r').parse().ok())
+            .ok_or_else(|| invalid(i + 1, "the id isn't a non-negative integer"))?;
+        ids.insert(line[..split].to_vec(), id);
+    }
+    let mut seen = HashSet::default();
+    if let Some(id) = ids.values().find(|&&id| !seen.insert(id)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("id {id} is given to more than one station")));
+    }
+    Ok(ids)
+}
+
+/// outputs the sorted report from a summary table, as configured by `options`
+pub fn report_with<W: Write>(table: &Table, options: &ReportOptions, out: &mut W) -> io::Result<()> {
+    let known = |k: &[u8]| options.known.as_ref().is_none_or(|known| known.contains(k));
+    let style = Style::new(options);
+    let hot = |v: &Sample| options.mean_above.is_none_or(|threshold| style.value(v.mean()) > threshold);
+    let missing = match (&options.known, options.show_missing, options.mean_above) {
+        (Some(known), true, None) => Some(known.iter().filter(|k| !table.contains_key(*k))),
+        _ => None,
+    };
+    let valid = |k: &[u8]| std::str::from_utf8(k).is_ok();
+    let selected: Vec<_> = table.iter()
+        .filter(|(k, v)| known(k) && hot(v))
+        .map(|(k, v)| (k.as_slice(), Some(v)))
+        .chain(missing.into_iter().flatten().map(|k| (k.as_slice(), None)))
+        .filter(|(k, _)| options.utf8_errors != Utf8Errors::Skip || valid(k))
+        .collect();
+    if options.utf8_errors == Utf8Errors::Fail {
+        if let Some((k, _)) = selected.iter().find(|(k, _)| !valid(k)) {
+            let name = String::from_utf8_lossy(k);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("station name {name:?} isn't valid UTF-8")));
+        }
+    }
+    let selected = selected.into_iter();
+    let mut entries = match &options.ids {
+        None => sorted_entries(selected),
+        Some(ids) => {
+            let (mapped, unmapped): (Vec<_>, Vec<_>) = selected.partition(|(k, _)| ids.contains_key(*k));
+            let mut mapped: Vec<_> = mapped.into_iter().map(|(k, v)| (ids[k], v)).collect();
+            mapped.sort_unstable_by_key(|(id, _)| *id);
+            let mut entries: Vec<_> = mapped.into_iter().map(|(id, v)| (Cow::Owned(id.to_string()), v)).collect();
+            if options.unmapped == Unmapped::ByName {
+                entries.extend(sorted_entries(unmapped.into_iter()));
+            }
+            entries
+        }
+    };
+    if !options.sort.is_empty() {
+        entries.sort_by(|(l_name, l), (r_name, r)| {
+            options.sort.iter()
+                .map(|by| by.compare((l_name, *l), (r_name, *r)))
+                .find(|ord| ord.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+    let table_total = options.total_key.as_ref().map(|key| (key, total(table)));
+    if let Some((key, table_total)) = &table_total {
+        entries.push((Cow::Borrowed(key.as_str()), Some(table_total)));
+    }
+    if let Some(width) = options.name_width {
+        for (city, _) in entries.iter_mut().filter(|(city, _)| city.len() > width) {
+            *city = Cow::Owned(format!("{}…", &city[..city.floor_char_boundary(width)]));
+        }
+    }
+    let mut out = io::BufWriter::with_capacity(options.buffer_size.unwrap_or(REPORT_BUFFER), out);
+    write_entries(&mut out, style, options.format, entries.iter().map(|(city, record)| (city.as_ref(), *record)))?;
+    out.flush()
+}
+
+/// Decodes the names and sorts the entries by them. Names that decode the same (different invalid
+/// bytes both become `\u{FFFD}`) are ordered by their raw bytes, so the order never depends on the
+/// table's iteration order.
+fn sorted_entries<'a>(
+    entries: impl Iterator<Item=(&'a [u8], Option<&'a Sample>)>,
+) -> Vec<(Cow<'a, str>, Option<&'a Sample>)> {
+    let mut entries: Vec<_> = entries
+        .map(|(k, v)| (String::from_utf8_lossy(k), k, v))
+        .collect();
+    entries.sort_unstable_by(|(l, l_raw, _), (r, r_raw, _)| l.cmp(r).then_with(|| l_raw.cmp(r_raw)));
+    entries.into_iter().map(|(name, _, v)| (name, v)).collect()
+}
+
+/// Outputs the report for a table from `produce_table_int`, like `report` but with the stations
+/// in ascending order of id
+pub fn report_int<W: Write>(table: &IntTable, out: &mut W) -> io::Result<()> {
+    let mut entries: Vec<_> = table.iter().collect();
+    entries.sort_unstable_by_key(|(id, _)| **id);
+    let names: Vec<_> = entries.iter().map(|(id, _)| id.to_string()).collect();
+    let entries = names.iter().zip(entries).map(|(name, (_, sample))| (name.as_str(), Some(sample)));
+    write_entries(out, Style::new(&ReportOptions::default()), ReportFormat::Braces, entries)
+}
+
+/// Produces the same report as `report`, as a string
+pub fn report_to_string(table: &Table) -> String {
+    let mut out = Vec::new();
+    report(table, &mut out).expect("writing to a Vec can't fail");
+    String::from_utf8(out).expect("the report is built from strings")
+}
+
+/// The SHA-256 of a report's exact bytes (say from `report_to_string`, or written by `report_with`
+/// into a `Vec`), in lowercase hex: the same as `sha256sum` prints for the output. For pinning a
+/// report in CI, where any change to it, down to a byte, should be noticed.
+///
+/// ```
+/// let table = onebrc::produce_table_bytes(b"Oslo;2.0\n");
+/// let report = onebrc::report_to_string(&table);
+/// assert_eq!(report, "{Oslo=2.0/2.0/2.0}\n");
+/// assert_eq!(onebrc::report_sha256(report.as_bytes()).len(), 64);
+/// assert_eq!(onebrc::report_sha256(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+/// ```
+pub fn report_sha256(report: &[u8]) -> String {
+    sha256::sha256(report).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Outputs the report with the stations in the given order, rather than alphabetically. Listed
+/// stations that aren't in the table are left out, and a station listed twice is only reported
+/// the first time. Stations in the table but not in `order` follow the listed ones, alphabetically.
+pub fn report_ordered<W: Write>(table: &Table, order: &[&[u8]], out: &mut W) -> io::Result<()> {
+    let mut listed: Vec<(String, Option<&Sample>)> = Vec::with_capacity(order.len());
+    let mut seen = HashSet::default();
+    for &name in order {
+        if let Some(record) = table.get(name) {
+            if seen.insert(name) {
+                listed.push((String::from_utf8_lossy(name).to_string(), Some(record)));
+            }
+        }
+    }
+    let rest = sorted_entries(table.iter()
+        .filter(|(k, _)| !seen.contains(k.as_slice()))
+        .map(|(k, v)| (k.as_slice(), Some(v))));
+
+    let entries = listed.iter().map(|(city, record)| (city.as_str(), *record))
+        .chain(rest.iter().map(|(city, record)| (city.as_ref(), *record)));
+    write_entries(out, Style::new(&ReportOptions::default()), ReportFormat::Braces, entries)
+}
+
+/// How a report prints values
+#[derive(Clone, Copy)]
+struct Style {
+    kelvin: bool,
+    rounding: Rounding,
+    counts: bool,
+    decimal_comma: bool,
+}
+
+impl Style {
+    fn new(options: &ReportOptions) -> Self {
+        Style {
+            kelvin: options.kelvin,
+            rounding: options.rounding,
+            counts: options.counts,
+            decimal_comma: options.decimal_comma,
+        }
+    }
+
+    /// A printed value (from `value` or `sum`) as text, with the decimal separator
+    fn text(self, v: f64) -> Tenths {
+        Tenths(v, self.decimal_comma)
+    }
+
+    /// A (Celsius) value from a table as it's printed: converted, and rounded to one decimal place
+    fn value(self, v: f32) -> f64 {
+        let v = if self.kelvin { v as f64 + 273.15 } else { v as f64 };
+        self.rounding.tenths(v)
+    }
+
+    /// A sample's sum as it's printed, converted like each of its values
+    fn sum(self, sample: &Sample) -> f64 {
+        let sum = sample.sum;
+        let sum = if self.kelvin { sum + 273.15 * sample.count as f64 } else { sum };
+        self.rounding.tenths(sum)
+    }
+}
+
+/// A value formatted to one decimal place, with a decimal comma if the flag is set
+struct Tenths(f64, bool);
+
+impl fmt::Display for Tenths {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.1 {
+            false => write!(f, "{:.1}", self.0),
+            true => f.write_str(&format!("{:.1}", self.0).replace('.', ",")),
+        }
+    }
+}
+
+/// Writes the report for the given entries, in order. An entry without a sample is written as
+/// `name=MISSING` (or with `null` values), and one with an empty sample is left out.
+fn write_entries<'a, W: Write>(
+    out: &mut W,
+    style: Style,
+    format: ReportFormat,
+    entries: impl Iterator<Item=(&'a str, Option<&'a Sample>)>,
+) -> io::Result<()> {
+    let entries = entries.filter(|(_, record)| !record.is_some_and(Sample::is_empty));
+    match format {
+        ReportFormat::Braces => write_braces(out, style, entries),
+        ReportFormat::Ndjson => write_ndjson(out, style, entries),
+        ReportFormat::Pretty => write_pretty(out, style, entries),
+        #[cfg(feature = "parquet")]
+        ReportFormat::Parquet => parquet::write_parquet(out, style, entries),
+    }
+}
+
+fn write_braces<'a, W: Write>(
+    out: &mut W,
+    style: Style,
+    entries: impl Iterator<Item=(&'a str, Option<&'a Sample>)>,
+) -> io::Result<()> {
+    write!(out, "{{")?;
+    let mut first = true;
+    for (city, record) in entries {
+        if !first {
+            write!(out, ", ")?;
+        } else {
+            first = false;
+        }
+        match record {
+            Some(record) => {
+                write!(
+                    out,
+                    "{city}={}/{}/{}",
+                    style.text(style.value(record.min)),
+                    style.text(style.value(record.mean())),
+                    style.text(style.value(record.max)),
+                )?;
+                if style.counts {
+                    write!(out, "/{}/{}", record.count, style.text(style.sum(record)))?;
+                }
+            }
+            None => write!(out, "{city}=MISSING")?,
+        }
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn write_ndjson<'a, W: Write>(
+    out: &mut W,
+    style: Style,
+    entries: impl Iterator<Item=(&'a str, Option<&'a Sample>)>,
+) -> io::Result<()> {
+    for (city, record) in entries {
+        write!(out, "{{\"station\":")?;
+        write_json_string(out, city)?;
+        match record {
+            Some(record) => {
+                write!(
+                    out,
+                    ",\"min\":{:.1},\"mean\":{:.1},\"max\":{:.1}",
+                    style.value(record.min), style.value(record.mean()), style.value(record.max),
+                )?;
+                if style.counts {
+                    write!(out, ",\"count\":{},\"sum\":{:.1}", record.count, style.sum(record))?;
+                }
+                writeln!(out, "}}")?;
+            }
+            None if style.counts => {
+                writeln!(out, ",\"min\":null,\"mean\":null,\"max\":null,\"count\":0,\"sum\":null}}")?
+            }
+            None => writeln!(out, ",\"min\":null,\"mean\":null,\"max\":null}}")?,
+        }
+    }
+    Ok(())
+}
+
+fn write_pretty<'a, W: Write>(
+    out: &mut W,
+    style: Style,
+    entries: impl Iterator<Item=(&'a str, Option<&'a Sample>)>,
+) -> io::Result<()> {
+    let mut header = vec!["station", "min", "mean", "max"];
+    if style.counts {
+        header.extend(["count", "sum"]);
+    }
+    // format every cell, and find each column's width (in characters) on the way
+    let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+    let rows: Vec<Vec<String>> = entries
+        .map(|(city, record)| {
+            let mut row = vec![city.to_string()];
+            match record {
+                Some(record) => {
+                    row.extend([record.min, record.mean(), record.max].map(|v| style.text(style.value(v)).to_string()));
+                    if style.counts {
+                        row.extend([record.count.to_string(), style.text(style.sum(record)).to_string()]);
+                    }
+                }
+                None => row.push("MISSING".to_string()),
+            }
+            for (width, cell) in widths.iter_mut().zip(&row) {
+                *width = (*width).max(cell.chars().count());
+            }
+            row
+        })
+        .collect();
+
+    for row in std::iter::once(header.iter().map(|h| h.to_string()).collect()).chain(rows) {
+        let mut line = String::new();
+        for (i, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            let pad = " ".repeat(width - cell.chars().count());
+            match i {
+                0 => line.push_str(&format!("{cell}{pad}")),
+                _ => line.push_str(&format!("  {pad}{cell}")),
+            }
+        }
+        writeln!(out, "{}", line.trim_end())?;
     }
-    writeln!(stdout, "}}")?;
     Ok(())
 }
+
+/// Writes `s` as a quoted JSON string
+fn write_json_string<W: Write>(out: &mut W, s: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{c}")?,
+        }
+    }
+    write!(out, "\"")
+}
+
+/// The first problem `check_report` found in a report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReportFault {
+    /// It isn't a single `{...}` line
+    NotAReport,
+    /// The entry isn't `name=min/mean/max` with one decimal place (optionally followed by
+    /// `/count/sum`), or `name=MISSING`
+    Malformed(String),
+    /// The station comes before the one before it
+    OutOfOrder { previous: String, name: String },
+    /// `read_counted_report` needs the station's count and sum, which the report doesn't have
+    NoCounts(String),
+}
+
+impl fmt::Display for ReportFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportFault::NotAReport => write!(f, "not a {{name=min/mean/max, ...}} report"),
+            ReportFault::Malformed(entry) => write!(f, "malformed entry: {entry}"),
+            ReportFault::OutOfOrder { previous, name } => write!(f, "{name} comes after {previous}"),
+            ReportFault::NoCounts(name) => {
+                write!(f, "{name} has no count and sum to merge by; write the report with counts")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReportFault {}
+
+/// Checks a report in the format written by `report`: one `{...}` line, its entries well-formed
+/// (each value with exactly one decimal place, and counts, if any, whole numbers) and in
+/// increasing order of name. Returns the number of entries. Equal names are allowed, since `report`
+/// writes them for stations whose invalid UTF-8 decodes the same (as `A\u{FFFD}`, say).
+///
+/// Names may contain `, ` (the value after the `=` ends at the next one), but not `=`, which would
+/// make them ambiguous.
+pub fn check_report(report: &str) -> Result<usize, ReportFault> {
+    let entries = report_entries(report)?;
+    let mut previous: Option<&str> = None;
+    for &(name, value) in &entries {
+        let fields: Vec<_> = value.split('/').collect();
+        let well_formed = value == "MISSING" || match fields[..] {
+            [min, mean, max] => [min, mean, max].into_iter().all(is_one_decimal),
+            [min, mean, max, count, sum] => [min, mean, max, sum].into_iter().all(is_one_decimal)
+                && !count.is_empty() && count.bytes().all(|b| b.is_ascii_digit()),
+            _ => false,
+        };
+        if !well_formed {
+            return Err(ReportFault::Malformed(format!("{name}={value}")));
+        }
+        if let Some(previous) = previous.filter(|&previous| previous > name) {
+            return Err(ReportFault::OutOfOrder { previous: previous.to_string(), name: name.to_string() });
+        }
+        previous = Some(name);
+    }
+    Ok(entries.len())
+}
+
+/// Reads a report written with `ReportOptions::counts` back into a table, to merge with others
+/// (`merge_all`) and report again: the merged min and max are exact, and so is the mean, up to
+/// the rounding of each part's sum to one decimal place. Every entry needs its count and sum;
+/// `MISSING` entries are skipped. The values are taken as they are, so the merged report should
+/// be written in the same units as the parts, and shouldn't include a `with_total` entry (it
+/// would be merged as a station), or names cut short by `name_width`.
+///
+/// ```
+/// let a = onebrc::read_counted_report("{Cairo=10.0/15.0/20.0/2/30.0}").unwrap();
+/// let b = onebrc::read_counted_report("{Cairo=12.0/12.0/12.0/1/12.0, Oslo=MISSING}").unwrap();
+/// let merged = onebrc::merge_all([a, b]);
+/// assert_eq!(merged[b"Cairo".as_slice()].mean(), 14.0);
+/// assert_eq!(merged[b"Cairo".as_slice()].count(), 3);
+///
+/// assert!(onebrc::read_counted_report("{Cairo=10.0/15.0/20.0}").is_err());
+/// ```
+pub fn read_counted_report(report: &str) -> Result<Table, ReportFault> {
+    let mut table = Table::default();
+    for (name, value) in report_entries(report)? {
+        if value == "MISSING" {
+            continue;
+        }
+        let malformed = || ReportFault::Malformed(format!("{name}={value}"));
+        let fields: Vec<_> = value.split('/').collect();
+        let &[min, _, max, count, sum] = &fields[..] else {
+            return Err(match fields.len() {
+                3 => ReportFault::NoCounts(name.to_string()),
+                _ => malformed(),
+            });
+        };
+        let parse = |v: &str| v.parse::<f32>().map_err(|_| malformed());
+        let count: u32 = count.parse().map_err(|_| malformed())?;
+        let (min, max) = (parse(min)?, parse(max)?);
+        // in f64, like the sample's own sum: an f32 sum would lose digits past 2^24
+        let sum: f64 = sum.parse().map_err(|_| malformed())?;
+        if count == 0 || min > max {
+            return Err(malformed());
+        }
+        table.entry(name.as_bytes().to_vec()).or_default().merge(&Sample::new(min, max, sum, count));
+    }
+    Ok(table)
+}
+
+/// Splits a report's `{...}` line into its entries' names and values
+fn report_entries(report: &str) -> Result<Vec<(&str, &str)>, ReportFault> {
+    let line = report.strip_suffix('\n').unwrap_or(report);
+    let mut rest = line.strip_prefix('{')
+        .and_then(|l| l.strip_suffix('}'))
+        .filter(|body| !body.contains('\n'))
+        .ok_or(ReportFault::NotAReport)?;
+
+    let mut entries = Vec::new();
+    while !rest.is_empty() {
+        let (name, after) = rest.split_once('=')
+            .ok_or_else(|| ReportFault::Malformed(rest.to_string()))?;
+        let (value, next) = after.split_once(", ").unwrap_or((after, ""));
+        if next.is_empty() && after.len() != value.len() {
+            // a trailing ", "
+            return Err(ReportFault::Malformed(rest.to_string()));
+        }
+        entries.push((name, value));
+        rest = next;
+    }
+    Ok(entries)
+}
+
+/// Whether the text is a number as written by `{:.1}`, like `-12.3`
+fn is_one_decimal(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    matches!(digits.split_once('.'), Some((int, frac))
+        if !int.is_empty() && int.bytes().all(|b| b.is_ascii_digit())
+            && frac.len() == 1 && frac.as_bytes()[0].is_ascii_digit())
+}
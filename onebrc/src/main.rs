@@ -1,73 +1,620 @@
 use std::env::args;
 use std::error::Error;
+use std::process::exit;
 use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
-use std::sync::mpsc;
-use onebrc::Table;
+use std::io::{BufReader, BufWriter, Write};
+use onebrc::{EmptyNamePolicy, IngestOptions, LongNamePolicy, InputChecksum, NonFinitePolicy, ReportFormat, ReportOptions, Rounding, RunOptions, StationDiff, Unmapped, Utf8Errors};
 
 fn main() -> Result<(), Box<dyn Error>> {
-    if let [_, filename, ..] = &args().collect::<Vec<_>>()[..] {
-        let mut infile = File::open(filename)?;
-
-        let file_len = infile.seek(SeekFrom::End(0))?;
-        let core_count: usize = std::thread::available_parallelism().unwrap().into();
-        let num_chunks = core_count as u64;
-        let mut splits: Vec<_> = (1..num_chunks).map(|i| i * (file_len/num_chunks))
-            .map(|pos| {
-                // seek forward to align with the start of a line
-                infile.seek(SeekFrom::Start(pos)).unwrap();
-                let mut b = [0u8; 1];
-                while b[0] != b'\n' {
-                    infile.read(&mut b[..]).unwrap();
+    let mut filename = None;
+    let mut options = IngestOptions::default();
+    let mut round_trip_check = false;
+    let mut thread_stats = false;
+    let mut dir = None;
+    let mut report_options = ReportOptions::default();
+    let mut rounding = Rounding::default();
+    let mut spill_dir = None;
+    let mut spill_threshold = None;
+    let mut group_by_prefix = None;
+    let mut group_depth = 1;
+    let mut check_report = None;
+    let mut with_total = false;
+    let mut total_key = String::from("__ALL__");
+    let mut from_end = false;
+    let mut single_bucket = false;
+    let mut variance = false;
+    let mut explain_splits = false;
+    let mut threads = None;
+    let mut reducers = None;
+    let mut repeat = 1;
+    let mut qa = false;
+    let mut show_missing = false;
+    let mut streaming_output = false;
+    let mut zstd = false;
+    let mut out_path = None;
+    let mut progress_bar = false;
+    let mut diff = None;
+    let mut fds: Vec<u32> = Vec::new();
+    let mut sample_rate = None;
+    let mut sample_seed = 0;
+    let mut max_name_bytes = None;
+    let mut long_names = LongNamePolicy::Reject;
+    let mut merge_reports = false;
+    let mut reports = Vec::new();
+    let mut id_map = None;
+    let mut unmapped = Unmapped::ByName;
+    let mut verify_input: Option<InputChecksum> = None;
+    let mut report_hash = false;
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fahrenheit" => options = options.fahrenheit(true),
+            "--zstd" => zstd = true,
+            "--repeat" => repeat = args.next().ok_or("--repeat needs a count")?.parse()?,
+            "--reducers" => reducers = Some(parse_threads(&args.next().ok_or("--reducers needs a thread count")?)?),
+            "--progress-bar" => progress_bar = true,
+            "--out" => out_path = Some(args.next().ok_or("--out needs a file")?),
+            "--cr" => options = options.cr_line_endings(true),
+            "--decimal-comma" => options = options.decimal_point(b','),
+            "--scientific" => options = options.scientific(true),
+            "--every" => {
+                let n = args.next().ok_or("--every needs a row count")?.parse()?;
+                options = options.every(n);
+            }
+            "--sample-rate" => sample_rate = Some(args.next().ok_or("--sample-rate needs a fraction")?.parse()?),
+            "--sample-seed" => sample_seed = args.next().ok_or("--sample-seed needs a seed")?.parse()?,
+            "--non-finite" => {
+                let policy = match args.next().as_deref() {
+                    Some("reject") => NonFinitePolicy::Reject,
+                    Some("skip") => NonFinitePolicy::Skip,
+                    _ => return Err("--non-finite needs reject or skip".into()),
+                };
+                options = options.non_finite(policy);
+            }
+            "--empty-names" => {
+                let policy = match args.next().as_deref() {
+                    Some("accept") => EmptyNamePolicy::Accept,
+                    Some("reject") => EmptyNamePolicy::Reject,
+                    _ => return Err("--empty-names needs accept or reject".into()),
+                };
+                options = options.empty_names(policy);
+            }
+            "--max-name-bytes" => max_name_bytes = Some(args.next().ok_or("--max-name-bytes needs a byte count")?.parse()?),
+            "--long-names" => {
+                long_names = match args.next().as_deref() {
+                    Some("reject") => LongNamePolicy::Reject,
+                    Some("truncate") => LongNamePolicy::Truncate,
+                    _ => return Err("--long-names needs reject or truncate".into()),
+                };
+            }
+            "--skip-bad-lines" => options = options.skip_bad_lines(true),
+            "--max-stations" => {
+                options = options.max_stations(args.next().ok_or("--max-stations needs a station count")?.parse()?);
+            }
+            "--qa" => {
+                qa = true;
+                options = options.qa(true);
+            }
+            "--verify-input" => {
+                verify_input = Some(args.next().ok_or("--verify-input needs a checksum from gen --verify-stream")?.parse()?);
+                options = options.checksum(true);
+            }
+            "--fd" => fds.push(args.next().ok_or("--fd needs a file descriptor")?.parse()?),
+            "--diff" => {
+                let old = args.next().ok_or("--diff needs an old and a new file")?;
+                diff = Some((old, args.next().ok_or("--diff needs an old and a new file")?));
+            }
+            "--merge-reports" => merge_reports = true,
+            "--count" => report_options = report_options.counts(true),
+            "--check-report" => check_report = Some(args.next().ok_or("--check-report needs a report file")?),
+            "--limit-rows" => {
+                let n = args.next().ok_or("--limit-rows needs a row count")?.parse()?;
+                options = options.limit_rows(n);
+            }
+            "--round-trip-check" => round_trip_check = true,
+            "--thread-stats" => thread_stats = true,
+            "--threads" => threads = Some(parse_threads(&args.next().ok_or("--threads needs a thread count")?)?),
+            "--from-end" => from_end = true,
+            "--single-bucket" => single_bucket = true,
+            "--variance" => variance = true,
+            "--explain-splits" => explain_splits = true,
+            "--dir" => dir = Some(args.next().ok_or("--dir needs a directory")?),
+            "--known-stations" => {
+                let registry = File::open(args.next().ok_or("--known-stations needs a file")?)?;
+                report_options = report_options.known_stations(onebrc::read_station_list(BufReader::new(registry))?);
+            }
+            "--id-map" => {
+                let ids = File::open(args.next().ok_or("--id-map needs a file")?)?;
+                id_map = Some(onebrc::read_id_map(BufReader::new(ids))?);
+            }
+            "--unmapped" => {
+                unmapped = match args.next().as_deref() {
+                    Some("name") => Unmapped::ByName,
+                    Some("skip") => Unmapped::Skip,
+                    _ => return Err("--unmapped needs name or skip".into()),
+                };
+            }
+            "--show-missing" => {
+                show_missing = true;
+                report_options = report_options.show_missing(true);
+            }
+            "--streaming-output" => streaming_output = true,
+            "--group-by-prefix" => {
+                match args.next().ok_or("--group-by-prefix needs a delimiter")?.as_bytes() {
+                    &[delimiter] => group_by_prefix = Some(delimiter),
+                    _ => return Err("--group-by-prefix needs a single-byte delimiter".into()),
                 }
-                infile.stream_position().unwrap()
-            })
-            .collect();
-        drop(infile);
-
-        splits.insert(0, 0);
-        splits.push(u64::MAX);
-
-        let infiles: Vec<_> = splits.windows(2)
-            .map(|splits| {
-                let split = splits[0];
-                let len = splits[1] - splits[0];
-                let mut f = File::open(filename).expect("reopen failed");
-                f.seek(SeekFrom::Start(split)).unwrap();
-                f.take(len)
-            })
-            .collect();
-
-        let (tx, rx) = mpsc::channel::<Table>();
-        std::thread::scope(|s| {
-            s.spawn(move || {
-                let final_table = rx.iter().reduce(|mut l, r| {
-                    r.into_iter().for_each(|(k, r)| {
-                        let e = l.entry(k).or_default();
-                        e.merge(&r);
-                    });
-                    l
-                })
-                    .unwrap();
-                onebrc::report(&final_table).unwrap();
-            });
-
-
-            infiles.into_iter()
-                .for_each(|f| {
-                    let tx = tx.clone();
-                    s.spawn(move || {
-                        let buf: BufReader<_> = BufReader::with_capacity(2 * 1024 * 1024, f);
-                        let t = onebrc::produce_table(buf);
-                        tx.send(t).expect("Send error")
-                    });
-                });
-            drop(tx);
-        });
+            }
+            "--group-depth" => group_depth = args.next().ok_or("--group-depth needs a depth")?.parse()?,
+            "--mean-above" => {
+                let threshold = args.next().ok_or("--mean-above needs a temperature")?.parse()?;
+                report_options = report_options.mean_above(threshold);
+            }
+            "--format" => {
+                let format = match args.next().as_deref() {
+                    Some("braces") => ReportFormat::Braces,
+                    Some("ndjson") => ReportFormat::Ndjson,
+                    Some("pretty") => ReportFormat::Pretty,
+                    #[cfg(feature = "parquet")]
+                    Some("parquet") => ReportFormat::Parquet,
+                    #[cfg(not(feature = "parquet"))]
+                    Some("parquet") => return Err("--format parquet needs onebrc built with the parquet feature".into()),
+                    _ => return Err("--format needs braces, ndjson, pretty or parquet".into()),
+                };
+                report_options = report_options.format(format);
+            }
+            "--rounding" => {
+                rounding = match args.next().as_deref() {
+                    Some("half-up") => Rounding::HalfUp,
+                    Some("half-even") => Rounding::HalfEven,
+                    Some("truncate") => Rounding::Truncate,
+                    Some("ceil") => Rounding::Ceil,
+                    Some("floor") => Rounding::Floor,
+                    _ => return Err("--rounding needs half-up, half-even, truncate, ceil or floor".into()),
+                };
+                report_options = report_options.rounding(rounding);
+            }
+            "--locale" => {
+                let decimal_comma = match args.next().as_deref() {
+                    Some("en") => false,
+                    Some("de") => true,
+                    _ => return Err("--locale needs en or de".into()),
+                };
+                report_options = report_options.decimal_comma(decimal_comma);
+            }
+            "--utf8-errors" => {
+                let policy = match args.next().as_deref() {
+                    Some("fail") => Utf8Errors::Fail,
+                    Some("replace") => Utf8Errors::Replace,
+                    Some("skip") => Utf8Errors::Skip,
+                    _ => return Err("--utf8-errors needs fail, replace or skip".into()),
+                };
+                report_options = report_options.utf8_errors(policy);
+            }
+            "--report-hash" => report_hash = true,
+            "--sort" => {
+                let keys = args.next().ok_or("--sort needs keys, like mean:desc,name")?
+                    .split(',')
+                    .map(|key| key.parse())
+                    .collect::<Result<Vec<onebrc::SortBy>, _>>()?;
+                report_options = report_options.sort_by(keys);
+            }
+            "--output-buffer-size" => {
+                let bytes = args.next().ok_or("--output-buffer-size needs a byte count")?.parse()?;
+                report_options = report_options.buffer_size(bytes);
+            }
+            "--pretty" => report_options = report_options.format(ReportFormat::Pretty),
+            "--kelvin" => report_options = report_options.kelvin(true),
+            "--with-total" => with_total = true,
+            "--total-key" => total_key = args.next().ok_or("--total-key needs a name")?,
+            "--name-width" => {
+                let width = args.next().ok_or("--name-width needs a byte count")?.parse()?;
+                report_options = report_options.name_width(width);
+            }
+            "--spill-dir" => spill_dir = Some(args.next().ok_or("--spill-dir needs a directory")?),
+            "--spill-threshold" => {
+                spill_threshold = Some(args.next().ok_or("--spill-threshold needs a station count")?.parse()?);
+            }
+            _ if arg.starts_with("--") => {
+                return Err(format!("unknown option {arg} (run onebrc with no arguments for usage)").into())
+            }
+            _ if merge_reports => reports.push(arg),
+            _ if filename.is_none() => filename = Some(arg),
+            _ => return Err(format!("unexpected argument {arg:?}: there's only one input file").into()),
+        }
+    }
+
+    // these check or report on the run over a single input file, which the other modes don't make
+    let single_file_only = [
+        (verify_input.is_some(), "--verify-input"),
+        (qa, "--qa"),
+        (thread_stats, "--thread-stats"),
+        (variance, "--variance"),
+        (round_trip_check, "--round-trip-check"),
+    ];
+    let other_modes = [
+        (check_report.is_some(), "--check-report"),
+        (!fds.is_empty(), "--fd"),
+        (diff.is_some(), "--diff"),
+        (merge_reports, "--merge-reports"),
+        (streaming_output, "--streaming-output"),
+        (dir.is_some(), "--dir"),
+    ];
+    let first_set = |flags: &[(bool, &'static str)]| flags.iter().find(|(set, _)| *set).map(|&(_, flag)| flag);
+    if let (Some(flag), Some(mode)) = (first_set(&single_file_only), first_set(&other_modes)) {
+        return Err(format!("{flag} only works on a single input file, so it can't be used with {mode}").into());
+    }
+
+    if with_total {
+        report_options = report_options.with_total(total_key);
+    }
+    if let Some(ids) = id_map {
+        report_options = report_options.id_map(ids, unmapped);
+    }
+
+    if let Some(max) = max_name_bytes {
+        options = options.max_name_bytes(max, long_names);
+    }
+    if let Some(rate) = sample_rate {
+        options = options.sample_rate(rate, sample_seed);
+    }
+    // --single-bucket reports every row as one ALL station, for profiling without the hash table
+    let mut run_options = RunOptions::default().ingest(options).from_end(from_end).single_bucket(single_bucket);
+    // --threads, then ONEBRC_THREADS, then the available parallelism (RunOptions' default)
+    let threads = match threads {
+        Some(n) => Some(n),
+        None => std::env::var("ONEBRC_THREADS").ok().map(|n| parse_threads(&n)).transpose()
+            .map_err(|e| format!("ONEBRC_THREADS: {e}"))?,
+    };
+    if let Some(threads) = threads {
+        run_options = run_options.threads(threads);
+    }
+    if let Some(reducers) = reducers {
+        run_options = run_options.reducers(reducers);
+    }
+    if let Some(dir) = spill_dir {
+        run_options = run_options.spill_dir(dir);
+    }
+    if let Some(stations) = spill_threshold {
+        run_options = run_options.spill_threshold(stations);
+    }
+
+    if let Some(report) = &check_report {
+        match onebrc::check_report(&std::fs::read_to_string(report)?) {
+            Ok(n) => println!("{report}: {n} stations, in order"),
+            Err(fault) => {
+                eprintln!("{report}: {fault}");
+                exit(1);
+            }
+        }
+        Ok(())
+    } else if !fds.is_empty() {
+        if !cfg!(unix) {
+            return Err("--fd is only supported on Unix".into());
+        }
+        // reopening /dev/fd/N gives a File for the inherited descriptor without taking ownership
+        // of it unsafely
+        let streams = fds.iter()
+            .map(|fd| File::open(format!("/dev/fd/{fd}")).map_err(|e| format!("--fd {fd}: {e}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        let table = onebrc::run_streams(streams, &run_options)?;
+        let mut out = output(out_path.as_deref())?;
+        write_report(&table, &report_options, report_hash, &mut out)?;
+        out.flush()?;
+        Ok(())
+    } else if let Some((old, new)) = &diff {
+        let old = onebrc::run(old, &run_options)?;
+        let new = onebrc::run(new, &run_options)?;
+        let mut out = output(out_path.as_deref())?;
+        // values as the two reports would print them, and changes as the differences between those
+        let tenths = |v: f32| rounding.tenths(v as f64);
+        let summary = |s: &onebrc::Sample| {
+            format!("{:.1}/{:.1}/{:.1}, {} rows", tenths(s.min()), tenths(s.mean()), tenths(s.max()), s.count())
+        };
+        for d in onebrc::diff_tables(&old, &new) {
+            let name = String::from_utf8_lossy(d.name());
+            match &d {
+                StationDiff::OnlyInA(key) => writeln!(out, "- {name}: removed (was {})", summary(&old[key]))?,
+                StationDiff::OnlyInB(key) => writeln!(out, "+ {name}: added ({})", summary(&new[key]))?,
+                StationDiff::Changed { a, b, .. } => writeln!(
+                    out,
+                    "~ {name}: min {:+.1}, mean {:+.1}, max {:+.1}, count {:+}",
+                    tenths(b.min()) - tenths(a.min()), tenths(b.mean()) - tenths(a.mean()), tenths(b.max()) - tenths(a.max()),
+                    b.count() as i64 - a.count() as i64,
+                )?,
+            }
+        }
+        out.flush()?;
+        Ok(())
+    } else if merge_reports {
+        let mut parts = Vec::with_capacity(reports.len());
+        for report in &reports {
+            match onebrc::read_counted_report(&std::fs::read_to_string(report)?) {
+                Ok(table) => parts.push(table),
+                Err(fault) => {
+                    eprintln!("{report}: {fault}");
+                    exit(1);
+                }
+            }
+        }
+        let mut out = output(out_path.as_deref())?;
+        write_report(&onebrc::merge_all(parts), &report_options, report_hash, &mut out)?;
+        out.flush()?;
+        Ok(())
+    } else if let (true, Some(filename)) = (streaming_output, &filename) {
+        // each shard's stations are complete once it's merged, so write them then, as NDJSON:
+        // one line per station, in no particular order overall
+        if with_total || show_missing || group_by_prefix.is_some() {
+            return Err("--streaming-output can't be used with --with-total, --show-missing or --group-by-prefix".into());
+        }
+        let report_options = report_options.format(ReportFormat::Ndjson);
+        let shards = threads.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+        let mut out = output(out_path.as_deref())?;
+        onebrc::run_partitioned_streaming(filename, &run_options, shards, |_, shard| {
+            onebrc::report_with(&shard, &report_options, &mut out)?;
+            Ok(out.flush()?)
+        })?;
+        Ok(())
+    } else if let Some(dir) = &dir {
+        let mut table = onebrc::run_dir(dir, &run_options)?;
+        if let Some(delimiter) = group_by_prefix {
+            table = onebrc::group_by_prefix(&table, delimiter, group_depth);
+        }
+        let mut out = output(out_path.as_deref())?;
+        write_report(&table, &report_options, report_hash, &mut out)?;
+        out.flush()?;
+        Ok(())
+    } else if let Some(filename) = &filename {
+        if explain_splits {
+            match onebrc::explain_splits(filename, &run_options) {
+                Ok(splits) => {
+                    eprintln!("{} chunks", splits.len());
+                    for (i, s) in splits.iter().enumerate() {
+                        eprintln!("chunk {i}: bytes {}..{} ({} bytes), start moved {} bytes to a row",
+                                  s.start, s.end, s.end - s.start, s.skipped);
+                    }
+                }
+                // e.g. a FIFO, which is read whole
+                Err(e) => eprintln!("can't split {filename}: {e}"),
+            }
+        }
+        let zstd = zstd || cfg!(feature = "zstd") && filename.ends_with(".zst");
+        if zstd && (variance || round_trip_check) {
+            return Err("--variance and --round-trip-check need an uncompressed file".into());
+        }
+        if repeat > 1 && (zstd || progress_bar || round_trip_check) {
+            return Err("--repeat doesn't work with --zstd, --progress-bar or --round-trip-check".into());
+        }
+        let (table, stats) = if zstd {
+            let (table, stats) = run_zstd(filename, &run_options)?;
+            (table, vec![stats])
+        } else {
+            let (table, stats) = match progress_bar {
+                true => run_with_progress_bar(filename, &run_options)?,
+                false => onebrc::run_repeated(filename, &run_options, repeat)?,
+            };
+            if thread_stats {
+                for s in &stats {
+                    eprintln!("chunk {}: {} bytes from {}, {} rows in {:?}",
+                              s.chunk, s.bytes, s.offset, s.rows, s.elapsed);
+                }
+            }
+            (table, stats.into_iter().map(|s| s.ingest).collect())
+        };
+        let non_finite: u64 = stats.iter().map(|s| s.non_finite).sum();
+        if non_finite > 0 {
+            eprintln!("skipped {non_finite} non-finite values");
+        }
+        let bad_lines: u64 = stats.iter().map(|s| s.bad_lines).sum();
+        if bad_lines > 0 {
+            eprintln!("skipped {bad_lines} malformed lines");
+        }
+        if qa {
+            let mut found = onebrc::QaStats::default();
+            stats.iter().for_each(|s| found.merge(&s.qa));
+            for (finding, what) in [
+                (&found.out_of_range, "values outside ±99.9"),
+                (&found.extra_decimals, "values with more than one decimal place"),
+                (&found.empty, "empty names or values (rows with empty values left out)"),
+            ] {
+                let examples: Vec<_> = finding.examples.iter().map(|row| format!("{row:?}")).collect();
+                match finding.count {
+                    0 => eprintln!("qa: 0 {what}"),
+                    n => eprintln!("qa: {n} {what}, e.g. {}", examples.join(", ")),
+                }
+            }
+        }
+        if let Some(expected) = verify_input {
+            let mut read = InputChecksum::default();
+            stats.iter().for_each(|s| read.merge(&s.checksum));
+            if read != expected {
+                eprintln!("Input check failed: expected {expected}, read {read}");
+                exit(1);
+            }
+        }
+        let mut out = output(out_path.as_deref())?;
+        match group_by_prefix {
+            Some(delimiter) => {
+                let grouped = onebrc::group_by_prefix(&table, delimiter, group_depth);
+                write_report(&grouped, &report_options, report_hash, &mut out)?;
+            }
+            None => write_report(&table, &report_options, report_hash, &mut out)?,
+        }
+
+        if variance {
+            let variances = onebrc::run_variance(filename, &run_options, &table)?;
+            let mut variances: Vec<_> = variances.iter()
+                .map(|(name, v)| (String::from_utf8_lossy(name), v))
+                .collect();
+            variances.sort_unstable_by(|(l, _), (r, _)| l.cmp(r));
+            let entries: Vec<_> = variances.iter().map(|(name, v)| format!("{name}={v:.2}")).collect();
+            writeln!(out, "{{{}}}", entries.join(", "))?;
+        }
+        out.flush()?;
+
+        if round_trip_check {
+            let report = onebrc::report_to_string(&table);
+            // a different chunk count changes which rows are merged together, and in what order.
+            // The first run's count is whatever --threads or ONEBRC_THREADS said, else RunOptions'
+            // default; the second is always more than that.
+            let first = match threads {
+                Some(n) => n,
+                None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            };
+            let second = first * 2 + 1;
+            let again = onebrc::run(filename, &run_options.threads(second))?;
+            if onebrc::report_to_string(&again) != report {
+                eprintln!("Round trip check failed: reports differ between {first} and {second} chunks");
+                for diff in onebrc::diff_tables(&table, &again) {
+                    eprintln!("{diff}");
+                }
+                exit(1);
+            }
+        }
 
         Ok(())
     } else {
-        println!("Usage: onebrc <filename>");
+        println!("Usage: onebrc [--fahrenheit] [--kelvin] [--cr] [--decimal-comma] [--every N] [--limit-rows N]
+                [--scientific] [--sample-rate FRACTION [--sample-seed N]]
+                [--non-finite reject|skip] [--empty-names accept|reject]
+                [--max-name-bytes N [--long-names reject|truncate]] [--skip-bad-lines] [--qa]
+                [--max-stations N]
+                [--round-trip-check] [--threads N] [--reducers N] [--thread-stats] [--from-end]
+                [--repeat N] [--variance] [--explain-splits] [--single-bucket]
+                [--spill-dir DIR [--spill-threshold N]]
+                [--known-stations FILE [--show-missing]] [--id-map FILE [--unmapped name|skip]]
+                [--name-width N] [--group-by-prefix DELIM [--group-depth N]]
+                [--with-total [--total-key NAME]] [--mean-above X] [--format FORMAT]
+                [--pretty] [--count] [--utf8-errors fail|replace|skip] [--report-hash]
+                [--rounding half-up|half-even|truncate|ceil|floor] [--locale en|de]
+                [--sort KEY[:asc|desc][,KEY[:asc|desc]]...] [--output-buffer-size BYTES]
+                [--verify-input CHECKSUM] [--zstd] [--out FILE] [--progress-bar]
+                [--streaming-output] <filename>
+       onebrc [--fahrenheit] [--cr] [--decimal-comma] --dir <directory of shards>
+       onebrc [input and report options] --fd N [--fd N]...   (Unix only; each read whole)
+       onebrc --check-report <report file>
+       onebrc [input options] [--out FILE] --diff <old file> <new file>
+       onebrc [--count] [report options] --merge-reports <reports written with --count>...
+
+With the zstd feature, --zstd (implied by a .zst file name) decompresses the input through the
+zstd tool, on one thread.
+
+--format is braces (the default), ndjson or pretty, or with the parquet feature, parquet: an
+Apache Parquet file of the stations' min, mean, max and count, best written with --out FILE.
+
+With the progress feature, --progress-bar shows how much of the file has been read, on stderr
+when both stderr and stdout are terminals.
+
+The thread count comes from --threads, or else the ONEBRC_THREADS environment variable, or else
+the available parallelism.
+
+--qa counts rows with values outside ±99.9, more than one decimal place, or an empty name or
+value, on stderr with a few examples, without failing the run.
+
+--verify-input, --qa, --thread-stats, --variance and --round-trip-check check or report on the run
+over a single input file, so they can't be combined with --dir, --fd, --diff, --merge-reports,
+--streaming-output or --check-report.
+
+--streaming-output writes each station as an NDJSON line as soon as it's complete, a shard of
+stations at a time, so the output isn't sorted: sort it afterwards if that matters.
+
+--max-stations N keeps at most N stations, folding the rarest into __OTHER__ when there'd be
+more: an approximation for inputs with too many stations to hold (see IngestOptions::max_stations).
+
+--repeat N reads the file N times over, as if it were N copies end to end, for load testing:
+counts and sums scale by N, the min and max don't.
+
+--locale de writes the report's numbers with a decimal comma (12,3), whatever the input had,
+except in NDJSON.
+
+--sort orders the report by the keys given, most significant first, then by name. Each KEY is
+name, min, mean, max or count, ascending unless followed by :desc. E.g. --sort mean:desc,name.
+
+--output-buffer-size sets how many bytes of the report are gathered per write (default 65536).
+0 writes each piece as it's formatted; the output is the same either way.
+
+--report-hash also prints the SHA-256 of the report's bytes on stderr, as sha256sum would.");
         Ok(())
     }
 }
+
+/// Writes the report, and with `--report-hash`, its SHA-256 to stderr: rendered once, so the hash
+/// is of exactly the bytes written
+fn write_report(table: &onebrc::Table, options: &ReportOptions, hash: bool, out: &mut impl Write) -> std::io::Result<()> {
+    if !hash {
+        return onebrc::report_with(table, options, out);
+    }
+    let mut report = Vec::new();
+    onebrc::report_with(table, options, &mut report)?;
+    out.write_all(&report)?;
+    eprintln!("report sha256: {}", onebrc::report_sha256(&report));
+    Ok(())
+}
+
+/// Where the report goes: the `--out` file (created, or truncated), or else stdout
+fn output(path: Option<&str>) -> std::io::Result<Box<dyn Write>> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    })
+}
+
+/// A thread count for `--threads` or `ONEBRC_THREADS`: a whole number, at least 1
+fn parse_threads(n: &str) -> Result<usize, String> {
+    match n.parse() {
+        Ok(0) | Err(_) => Err(format!("{n:?} isn't a thread count (a whole number, at least 1)")),
+        Ok(n) => Ok(n),
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn run_zstd(filename: &str, options: &RunOptions) -> Result<(onebrc::Table, onebrc::IngestStats), Box<dyn Error>> {
+    Ok(onebrc::run_zstd(filename, options)?)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn run_zstd(_: &str, _: &RunOptions) -> Result<(onebrc::Table, onebrc::IngestStats), Box<dyn Error>> {
+    Err("--zstd needs onebrc built with the zstd feature".into())
+}
+
+/// `run_with_stats`, drawing a bar of the bytes read so far on stderr, if both it and stdout are
+/// terminals (so not when the report is piped on) and the input has a length (a FIFO doesn't)
+#[cfg(feature = "progress")]
+fn run_with_progress_bar(filename: &str, options: &RunOptions) -> Result<(onebrc::Table, Vec<onebrc::WorkerStats>), Box<dyn Error>> {
+    use std::io::IsTerminal;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let total = std::fs::metadata(filename)?.len();
+    if !std::io::stderr().is_terminal() || !std::io::stdout().is_terminal() || total == 0 {
+        return Ok(onebrc::run_with_stats(filename, options)?);
+    }
+    let read = Arc::new(AtomicU64::new(0));
+    let done = AtomicBool::new(false);
+    let options = options.clone().progress(read.clone());
+    std::thread::scope(|s| {
+        let bar = s.spawn(|| {
+            const WIDTH: u64 = 40;
+            while !done.load(Ordering::Relaxed) {
+                let read = read.load(Ordering::Relaxed).min(total);
+                let filled = (read * WIDTH / total) as usize;
+                eprint!("\r[{}{}] {:3}% {:.1}/{:.1} MB", "#".repeat(filled), "-".repeat(WIDTH as usize - filled),
+                        read * 100 / total, read as f64 / 1e6, total as f64 / 1e6);
+                std::thread::park_timeout(Duration::from_millis(100));
+            }
+            // clear the bar
+            eprint!("\r{}\r", " ".repeat(WIDTH as usize + 30));
+        });
+        let result = onebrc::run_with_stats(filename, &options);
+        done.store(true, Ordering::Relaxed);
+        bar.thread().unpark();
+        bar.join().expect("the progress bar doesn't panic");
+        Ok(result?)
+    })
+}
+
+#[cfg(not(feature = "progress"))]
+fn run_with_progress_bar(_: &str, _: &RunOptions) -> Result<(onebrc::Table, Vec<onebrc::WorkerStats>), Box<dyn Error>> {
+    Err("--progress-bar needs onebrc built with the progress feature".into())
+}
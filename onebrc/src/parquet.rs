@@ -0,0 +1,275 @@
+//! With the `parquet` feature, `ReportFormat::Parquet`: the report's entries as an Apache Parquet
+//! file, for loading straight into analytics tools. The file is written here rather than through
+//! the `parquet` crate, as simply as the format allows: one row group (none if there are no rows),
+//! one uncompressed data page per column, PLAIN encoding, and the footer in Thrift's compact
+//! protocol. The schema is
+//!
+//! ```text
+//! message schema {
+//!   required binary station (STRING);
+//!   optional double min;
+//!   optional double mean;
+//!   optional double max;
+//!   required int64 count;
+//! }
+//! ```
+//!
+//! with a row per entry, in the report's order. The values are as the other formats print them
+//! (converted, and rounded to one decimal place); a station without data has null values and a
+//! count of 0. `ReportOptions::counts` doesn't change the schema.
+
+use std::io::{self, Write};
+use crate::{Sample, Style};
+
+const MAGIC: &[u8] = b"PAR1";
+
+// Parquet's enums, as the format's Thrift definition numbers them
+const BYTE_ARRAY: i32 = 6;
+const DOUBLE: i32 = 5;
+const INT64: i32 = 2;
+const REQUIRED: i32 = 0;
+const OPTIONAL: i32 = 1;
+const UTF8: i32 = 0;
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+const DATA_PAGE: i32 = 0;
+const UNCOMPRESSED: i32 = 0;
+
+pub(crate) fn write_parquet<'a, W: Write>(
+    out: &mut W,
+    style: Style,
+    entries: impl Iterator<Item=(&'a str, Option<&'a Sample>)>,
+) -> io::Result<()> {
+    let mut columns = [
+        Column::new("station", BYTE_ARRAY, REQUIRED),
+        Column::new("min", DOUBLE, OPTIONAL),
+        Column::new("mean", DOUBLE, OPTIONAL),
+        Column::new("max", DOUBLE, OPTIONAL),
+        Column::new("count", INT64, REQUIRED),
+    ];
+    let mut rows = 0;
+    for (city, record) in entries {
+        let [station, min, mean, max, count] = &mut columns;
+        station.values.extend((city.len() as u32).to_le_bytes());
+        station.values.extend(city.as_bytes());
+        match record {
+            Some(record) => {
+                for (column, v) in [min, mean, max].into_iter().zip([record.min, record.mean(), record.max]) {
+                    column.levels.push(1);
+                    column.values.extend(style.value(v).to_le_bytes());
+                }
+            }
+            None => [min, mean, max].into_iter().for_each(|column| column.levels.push(0)),
+        }
+        count.values.extend(record.map_or(0, |r| r.count as i64).to_le_bytes());
+        rows += 1;
+    }
+
+    // no row group at all for no rows, rather than empty pages
+    let mut file = MAGIC.to_vec();
+    let mut chunks = Vec::new();
+    if rows > 0 {
+        for column in &columns {
+            let start = file.len();
+            column.write_page(&mut file, rows);
+            chunks.push((start, file.len() - start));
+        }
+    }
+
+    let mut footer = Compact::default();
+    footer.begin();
+    footer.i32(1, 1);
+    footer.list(2, STRUCT, columns.len() + 1);
+    footer.begin();
+    footer.binary(4, b"schema");
+    footer.i32(5, columns.len() as i32);
+    footer.end();
+    for column in &columns {
+        footer.begin();
+        footer.i32(1, column.kind);
+        footer.i32(3, column.repetition);
+        footer.binary(4, column.name.as_bytes());
+        if column.kind == BYTE_ARRAY {
+            footer.i32(6, UTF8);
+            // logicalType: the STRING member of the LogicalType union, an empty struct
+            footer.field(10, STRUCT);
+            footer.begin();
+            footer.field(1, STRUCT);
+            footer.begin();
+            footer.end();
+            footer.end();
+        }
+        footer.end();
+    }
+    footer.i64(3, rows as i64);
+    footer.list(4, STRUCT, usize::from(rows > 0));
+    if rows > 0 {
+        footer.begin();
+        footer.list(1, STRUCT, columns.len());
+        for (column, &(start, len)) in columns.iter().zip(&chunks) {
+            footer.begin();
+            footer.i64(2, start as i64);
+            footer.field(3, STRUCT);
+            footer.begin();
+            footer.i32(1, column.kind);
+            footer.list(2, I32, 2);
+            footer.zigzag(PLAIN as i64);
+            footer.zigzag(RLE as i64);
+            footer.list(3, BINARY, 1);
+            footer.bytes(column.name.as_bytes());
+            footer.i32(4, UNCOMPRESSED);
+            footer.i64(5, rows as i64);
+            footer.i64(6, len as i64);
+            footer.i64(7, len as i64);
+            footer.i64(9, start as i64);
+            footer.end();
+            footer.end();
+        }
+        let total: usize = chunks.iter().map(|&(_, len)| len).sum();
+        footer.i64(2, total as i64);
+        footer.i64(3, rows as i64);
+        footer.end();
+    }
+    footer.binary(6, concat!("onebrc version ", env!("CARGO_PKG_VERSION")).as_bytes());
+    footer.end();
+
+    file.extend(&footer.out);
+    file.extend((footer.out.len() as u32).to_le_bytes());
+    file.extend(MAGIC);
+    out.write_all(&file)
+}
+
+/// One column's page, built up a row at a time
+struct Column {
+    name: &'static str,
+    kind: i32,
+    repetition: i32,
+    /// The PLAIN-encoded values, without the nulls
+    values: Vec<u8>,
+    /// For an optional column, 1 for each row with a value and 0 for each null
+    levels: Vec<u8>,
+}
+
+impl Column {
+    fn new(name: &'static str, kind: i32, repetition: i32) -> Self {
+        Column { name, kind, repetition, values: Vec::new(), levels: Vec::new() }
+    }
+
+    /// Writes the column's one data page, header and all
+    fn write_page(&self, file: &mut Vec<u8>, rows: usize) {
+        let mut data = Vec::new();
+        if self.repetition == OPTIONAL {
+            // the definition levels in the RLE/bit-packing hybrid, as RLE runs of a one-byte value,
+            // after their length. A required column has none.
+            let mut levels = Vec::new();
+            for run in self.levels.chunk_by(|a, b| a == b) {
+                varint(&mut levels, (run.len() as u64) << 1);
+                levels.push(run[0]);
+            }
+            data.extend((levels.len() as u32).to_le_bytes());
+            data.extend(levels);
+        }
+        data.extend(&self.values);
+
+        let mut header = Compact::default();
+        header.begin();
+        header.i32(1, DATA_PAGE);
+        header.i32(2, data.len() as i32);
+        header.i32(3, data.len() as i32);
+        header.field(5, STRUCT);
+        header.begin();
+        header.i32(1, rows as i32);
+        header.i32(2, PLAIN);
+        header.i32(3, RLE);
+        header.i32(4, RLE);
+        header.end();
+        header.end();
+        file.extend(&header.out);
+        file.extend(data);
+    }
+}
+
+// Thrift compact protocol type codes
+const I32: u8 = 5;
+const I64: u8 = 6;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const STRUCT: u8 = 12;
+
+/// A writer for Thrift's compact protocol, enough of it for Parquet's metadata
+#[derive(Default)]
+struct Compact {
+    out: Vec<u8>,
+    /// The last field id written in each struct that's open, innermost last, since each field
+    /// header holds the difference from it
+    last: Vec<i16>,
+}
+
+impl Compact {
+    /// Opens a struct, after its field header or as an element of a list
+    fn begin(&mut self) {
+        self.last.push(0);
+    }
+
+    fn end(&mut self) {
+        self.out.push(0);
+        self.last.pop();
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last.last_mut().expect("fields are inside a struct");
+        let delta = id - std::mem::replace(last, id);
+        match delta {
+            1..=15 => self.out.push((delta as u8) << 4 | kind),
+            _ => {
+                self.out.push(kind);
+                self.zigzag(id as i64);
+            }
+        }
+    }
+
+    fn zigzag(&mut self, v: i64) {
+        varint(&mut self.out, ((v << 1) ^ (v >> 63)) as u64);
+    }
+
+    fn bytes(&mut self, v: &[u8]) {
+        varint(&mut self.out, v.len() as u64);
+        self.out.extend(v);
+    }
+
+    fn i32(&mut self, id: i16, v: i32) {
+        self.field(id, I32);
+        self.zigzag(v as i64);
+    }
+
+    fn i64(&mut self, id: i16, v: i64) {
+        self.field(id, I64);
+        self.zigzag(v);
+    }
+
+    fn binary(&mut self, id: i16, v: &[u8]) {
+        self.field(id, BINARY);
+        self.bytes(v);
+    }
+
+    /// A list field's header; the `len` elements follow
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, LIST);
+        match len {
+            0..15 => self.out.push((len as u8) << 4 | kind),
+            _ => {
+                self.out.push(0xf0 | kind);
+                varint(&mut self.out, len as u64);
+            }
+        }
+    }
+}
+
+/// An unsigned LEB128 varint, as both Thrift's compact protocol and the RLE hybrid use
+fn varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
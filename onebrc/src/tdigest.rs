@@ -0,0 +1,145 @@
+//! A merging t-digest (Dunning & Ertl), for approximate quantiles of a station's values in bounded
+//! memory.
+//!
+//! The digest keeps the values as a sorted list of weighted centroids, and merges neighbouring
+//! centroids as long as that keeps each one within a size limit that shrinks towards the tails.
+//! With the default compression of 100 a digest holds at most a couple of hundred centroids (16
+//! bytes each) plus a buffer of up to 500 unmerged values, so about 10KB per station however many
+//! rows it has, compared to an exact histogram that grows with the number of distinct values.
+//! The error is best thought of in rank: the estimate for `q` is a value whose true quantile is
+//! within about 0.001 of `q` (at worst 0.002, after merges), everywhere from 0.001 to 0.999, and
+//! the min and max are exact. In value terms that's tightest in the dense middle of a
+//! distribution and loosest in sparse tails. Doubling the compression roughly halves the error and
+//! doubles the memory.
+
+const DEFAULT_COMPRESSION: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TDigest {
+    compression: f64,
+    /// Merged centroids, sorted by mean
+    centroids: Vec<Centroid>,
+    /// Values and centroids added since the last compression
+    buffer: Vec<Centroid>,
+    min: f64,
+    max: f64,
+}
+
+impl Default for TDigest {
+    fn default() -> Self {
+        TDigest::new(DEFAULT_COMPRESSION)
+    }
+}
+
+impl TDigest {
+    /// An empty digest. Higher `compression` keeps more centroids, for more accuracy.
+    pub fn new(compression: f64) -> Self {
+        TDigest {
+            compression,
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn add(&mut self, v: f64) {
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+        self.buffer.push(Centroid { mean: v, weight: 1.0 });
+        if self.buffer.len() as f64 >= 5.0 * self.compression {
+            self.compress();
+        }
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.buffer.extend_from_slice(&other.centroids);
+        self.buffer.extend_from_slice(&other.buffer);
+        if self.buffer.len() as f64 >= 5.0 * self.compression {
+            self.compress();
+        }
+    }
+
+    /// How many values the digest has seen
+    pub fn count(&self) -> f64 {
+        self.centroids.iter().chain(&self.buffer).map(|c| c.weight).sum()
+    }
+
+    /// The approximate value below which a fraction `q` (clamped to 0..=1) of the values lie, or
+    /// `None` for an empty digest
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if !self.buffer.is_empty() {
+            let mut compressed = self.clone();
+            compressed.compress();
+            return compressed.quantile(q);
+        }
+        let (first, last) = (self.centroids.first()?, self.centroids.last()?);
+        let total: f64 = self.centroids.iter().map(|c| c.weight).sum();
+        let target = q.clamp(0.0, 1.0) * total;
+
+        // each centroid's mean sits at the middle of its weight; interpolate between the middles,
+        // and out to the min and max at the ends
+        if target < first.weight / 2.0 {
+            return Some(lerp(self.min, first.mean, target / (first.weight / 2.0)));
+        }
+        let mut seen = 0.0;
+        for pair in self.centroids.windows(2) {
+            let left = seen + pair[0].weight / 2.0;
+            let right = seen + pair[0].weight + pair[1].weight / 2.0;
+            if target <= right {
+                return Some(lerp(pair[0].mean, pair[1].mean, (target - left) / (right - left)));
+            }
+            seen += pair[0].weight;
+        }
+        let left = total - last.weight / 2.0;
+        Some(lerp(last.mean, self.max, (target - left) / (last.weight / 2.0)))
+    }
+
+    /// Merges the buffer into the centroids
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.centroids);
+        all.append(&mut self.buffer);
+        all.sort_unstable_by(|a, b| a.mean.total_cmp(&b.mean));
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+
+        // the k1 scale function: a centroid may span at most one unit of k
+        let delta = self.compression;
+        let k = |q: f64| delta / (2.0 * std::f64::consts::PI) * (2.0 * q - 1.0).asin();
+        let k_inv = |k: f64| ((k.min(delta / 4.0) * 2.0 * std::f64::consts::PI / delta).sin() + 1.0) / 2.0;
+
+        let mut merged = Vec::with_capacity(all.len().min(2 * delta as usize));
+        let mut all = all.into_iter();
+        let mut current = all.next().expect("the buffer isn't empty");
+        let mut before = 0.0;
+        let mut limit = k_inv(k(0.0) + 1.0);
+        for next in all {
+            if (before + current.weight + next.weight) / total <= limit {
+                let weight = current.weight + next.weight;
+                current.mean += (next.mean - current.mean) * next.weight / weight;
+                current.weight = weight;
+            } else {
+                before += current.weight;
+                merged.push(current);
+                limit = k_inv(k(before / total) + 1.0);
+                current = next;
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t.clamp(0.0, 1.0)
+}
@@ -0,0 +1,34 @@
+//! With the `zstd` feature, aggregating zstd-compressed input without decompressing it to disk
+//! first. The decompression is done by the `zstd` command-line tool (which must be on the `PATH`),
+//! streaming into the reader on this thread, so it's single-threaded: a compressed stream can't
+//! be split into chunks.
+
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use crate::{Error, IngestStats, RunOptions, Table};
+
+/// Like `run_with_stats`, for a zstd-compressed file, read on one thread whatever
+/// `RunOptions::threads` says. The result is the same as running on the decompressed file with
+/// one thread. Fails with `Error::Io` if `zstd` can't be started, or reports an error (e.g. for
+/// input that isn't zstd).
+pub fn run_zstd<P: AsRef<Path>>(path: P, options: &RunOptions) -> Result<(Table, IngestStats), Error> {
+    let mut child = Command::new("zstd")
+        .args(["--decompress", "--stdout", "--quiet"])
+        .arg(path.as_ref())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let result = crate::produce_table_with_stats(BufReader::new(stdout), &options.ingest);
+    // on a parse error, stop zstd rather than waiting for it to write the rest
+    if result.is_err() {
+        let _ = child.kill();
+    }
+    let status = child.wait()?;
+    let result = result?;
+    if !status.success() {
+        return Err(io::Error::other(format!("zstd failed ({status})")).into());
+    }
+    Ok(result)
+}
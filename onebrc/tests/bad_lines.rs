@@ -0,0 +1,83 @@
+use std::io::BufReader;
+
+use onebrc::{EmptyNamePolicy, Error, IngestOptions, LongNamePolicy};
+
+const MIXED: &[u8] = b"Hamburg;12.0\nno separator\nBulawayo;8.9\nHamburg;\nHamburg;3x.4\n;1.0\nBulawayo;-1.1\nHamburg;34.2\nlast;1";
+
+/// Bad rows are counted and left out, and the good rows around them come through intact, however
+/// the pages happen to split the rows.
+#[test]
+fn bad_lines_are_skipped() {
+    let options = IngestOptions::default().skip_bad_lines(true);
+    for capacity in 1..=MIXED.len() {
+        let (table, stats) = onebrc::produce_table_with_stats(BufReader::with_capacity(capacity, MIXED), &options)
+            .unwrap_or_else(|e| panic!("{e} with {capacity}-byte pages"));
+        // the empty name is a station like any other
+        assert_eq!(table.len(), 3, "{capacity}-byte pages");
+        assert_eq!(stats.bad_lines, 4, "{capacity}-byte pages");
+        let hamburg = &table[b"Hamburg".as_slice()];
+        assert_eq!((hamburg.count(), hamburg.min(), hamburg.max()), (2, 12.0, 34.2), "{capacity}-byte pages");
+        let bulawayo = &table[b"Bulawayo".as_slice()];
+        assert_eq!((bulawayo.count(), bulawayo.min(), bulawayo.max()), (2, -1.1, 8.9), "{capacity}-byte pages");
+    }
+}
+
+#[test]
+fn bad_lines_fail_by_default() {
+    let result = onebrc::produce_table_with_stats(BufReader::new(MIXED), &IngestOptions::default());
+    assert!(matches!(result, Err(Error::MissingSeparator)));
+}
+
+/// An empty name is a station called `""` by default, reported first; rejected, it's an error, or
+/// a bad line when those are skipped.
+#[test]
+fn empty_names() {
+    let input: &[u8] = b"Oslo;2.0\n;12.3\n;-1.0\n";
+    let table = onebrc::produce_table_with(BufReader::new(input), &IngestOptions::default()).unwrap();
+    assert_eq!(onebrc::report_to_string(&table), "{=-1.0/5.7/12.3, Oslo=2.0/2.0/2.0}\n");
+
+    let reject = IngestOptions::default().empty_names(EmptyNamePolicy::Reject);
+    let result = onebrc::produce_table_with(BufReader::new(input), &reject);
+    assert!(matches!(result, Err(Error::EmptyName)));
+
+    let (table, stats) = onebrc::produce_table_with_stats(BufReader::new(input), &reject.skip_bad_lines(true)).unwrap();
+    assert_eq!(onebrc::report_to_string(&table), "{Oslo=2.0/2.0/2.0}\n");
+    assert_eq!(stats.bad_lines, 2);
+}
+
+/// A name over the limit is an error by default, or cut short at a character boundary
+#[test]
+fn long_names() {
+    // "Zürich" is 7 bytes, and 2 would split the ü
+    let input: &[u8] = "Oslo;2.0\nZürich;12.3\nZu;1.0\n".as_bytes();
+    let reject = IngestOptions::default().max_name_bytes(4, LongNamePolicy::Reject);
+    let result = onebrc::produce_table_with(BufReader::new(input), &reject);
+    assert!(matches!(result, Err(Error::NameTooLong)));
+
+    let (table, stats) = onebrc::produce_table_with_stats(BufReader::new(input), &reject.skip_bad_lines(true)).unwrap();
+    assert_eq!(onebrc::report_to_string(&table), "{Oslo=2.0/2.0/2.0, Zu=1.0/1.0/1.0}\n");
+    assert_eq!(stats.bad_lines, 1);
+
+    let truncate = IngestOptions::default().max_name_bytes(2, LongNamePolicy::Truncate);
+    let table = onebrc::produce_table_with(BufReader::new(input), &truncate).unwrap();
+    assert_eq!(onebrc::report_to_string(&table), "{Os=2.0/2.0/2.0, Z=12.3/12.3/12.3, Zu=1.0/1.0/1.0}\n");
+}
+
+/// QA counts each kind of odd row, with the first few as examples, and only leaves out the rows
+/// with no value
+#[test]
+fn qa_findings() {
+    let rows = b"Oslo;1.0\nOslo;123.4\n;5.0\nBergen;\nOslo;1.25\nX;-100.00\nBergen;-\nOslo;-99.9\nOslo;100.1\nY;100.0\n";
+    let options = IngestOptions::default().qa(true);
+    let (table, stats) = onebrc::produce_table_with_stats(BufReader::new(rows.as_slice()), &options).unwrap();
+    assert_eq!(table.len(), 4);
+    assert!(!table.contains_key(b"Bergen".as_slice()));
+
+    let qa = stats.qa;
+    assert_eq!(qa.out_of_range.count, 4);
+    assert_eq!(qa.out_of_range.examples, ["Oslo;123.4", "X;-100.00", "Oslo;100.1"]);
+    assert_eq!(qa.extra_decimals.count, 2);
+    assert_eq!(qa.extra_decimals.examples, ["Oslo;1.25", "X;-100.00"]);
+    assert_eq!(qa.empty.count, 3);
+    assert_eq!(qa.empty.examples, [";5.0", "Bergen;", "Bergen;-"]);
+}
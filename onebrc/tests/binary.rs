@@ -0,0 +1,46 @@
+#![cfg(feature = "binary")]
+
+use std::io::ErrorKind;
+
+/// The samples of `table` field for field, by name, to compare exactly
+fn fields(table: &onebrc::Table) -> Vec<(&[u8], u32, u32, u64, u32)> {
+    let mut fields: Vec<_> = table.iter()
+        .map(|(k, s)| (k.as_slice(), s.min().to_bits(), s.max().to_bits(), (s.mean_f64() * s.count() as f64).to_bits(), s.count()))
+        .collect();
+    fields.sort();
+    fields
+}
+
+/// A table written and read back is the same, to the bit
+#[test]
+fn round_trip() {
+    let rows: String = (0..20_000).map(|i| format!("Station{};{}.{}\n", i % 613, i % 199 - 99, i % 10)).collect();
+    let mut table = onebrc::produce_table_bytes(rows.as_bytes());
+    table.insert("Zürich".as_bytes().to_vec(), onebrc::Sample::new(-0.0, 1e-3, 123456789.3, 3_000_000));
+    table.insert(Vec::new(), onebrc::Sample::from(5.0));
+    let mut out = Vec::new();
+    onebrc::write_table_bin(&table, &mut out).unwrap();
+    let read = onebrc::read_table_bin(&mut out.as_slice()).unwrap();
+    assert_eq!(fields(&read), fields(&table));
+
+    let mut empty = Vec::new();
+    onebrc::write_table_bin(&onebrc::Table::default(), &mut empty).unwrap();
+    assert!(empty.is_empty());
+    assert!(onebrc::read_table_bin(&mut empty.as_slice()).unwrap().is_empty());
+}
+
+/// The layout the docs give, and input that stops partway through a station is an error
+#[test]
+fn format() {
+    let mut out = Vec::new();
+    onebrc::write_table_bin(&onebrc::produce_table_bytes(b"Oslo;-2.5\nOslo;3.0\n"), &mut out).unwrap();
+    let expected = [
+        &4u32.to_le_bytes()[..], b"Oslo", &(-2.5f32).to_le_bytes(), &3.0f32.to_le_bytes(), &0.5f64.to_le_bytes(),
+        &2u32.to_le_bytes(),
+    ].concat();
+    assert_eq!(out, expected);
+    for len in 1..out.len() {
+        let err = onebrc::read_table_bin(&mut &out[..len]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof, "{len} bytes");
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use onebrc::RunOptions;
+
+/// An input file in the temp directory, deleted when it's dropped, so it doesn't outlive a test
+/// whose assertions fail first
+struct TempFile(PathBuf);
+
+impl TempFile {
+    /// Writes `contents` to a file named after `test` and this process, so tests running at the
+    /// same time, here or in another run, each have their own
+    fn new(test: &str, contents: impl AsRef<[u8]>) -> Self {
+        let path = std::env::temp_dir().join(format!("onebrc-{test}-{}.txt", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        TempFile(path)
+    }
+}
+
+impl AsRef<Path> for TempFile {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// `n` rows from the generator with `seed`, in the input format
+fn generated(n: usize, seed: u64) -> String {
+    gen::gen_seeded(n, seed).map(|(city, temp)| format!("{city};{temp:.1}\n")).collect()
+}
+
+/// Generates a small file, aggregates it with a few different chunk counts, and checks the
+/// results against a straightforward f64 computation over the same rows.
+#[test]
+fn generated_file_matches_reference() {
+    let rows = generated(5000, 42);
+    let file = TempFile::new("reference", &rows);
+
+    // (min, max, sum, count) per station
+    let mut reference: BTreeMap<&str, (f64, f64, f64, u32)> = BTreeMap::new();
+    for row in rows.lines() {
+        let (city, temp) = row.split_once(';').unwrap();
+        let temp: f64 = temp.parse().unwrap();
+        let e = reference.entry(city).or_insert((f64::MAX, f64::MIN, 0.0, 0));
+        e.0 = e.0.min(temp);
+        e.1 = e.1.max(temp);
+        e.2 += temp;
+        e.3 += 1;
+    }
+
+    // The reports aren't compared with each other byte for byte: sums are floating point, so the
+    // merge order can nudge a mean that's exactly on a .x5 boundary either way.
+    for threads in [1, 3, 8] {
+        let table = onebrc::run(&file, &RunOptions::default().threads(threads)).unwrap();
+        assert_eq!(table.len(), reference.len());
+        for (city, (min, max, sum, count)) in &reference {
+            let sample = &table[city.as_bytes()];
+            assert_eq!(sample.count(), *count, "{city} with {threads} threads");
+            assert_eq!(format!("{:.1}", sample.min()), format!("{min:.1}"), "{city}");
+            assert_eq!(format!("{:.1}", sample.max()), format!("{max:.1}"), "{city}");
+            assert!((sample.mean() as f64 - sum / *count as f64).abs() < 0.01, "{city}");
+        }
+    }
+}
+
+/// The generator's hand-checked fixture gives exactly the report worked out for it
+#[test]
+fn fixture_matches_expected_report() {
+    let rows: String = gen::FIXTURE.iter().map(|(city, temp)| format!("{city};{temp:.1}\n")).collect();
+    let table = onebrc::produce_table_bytes(rows.as_bytes());
+    assert_eq!(onebrc::report_to_string(&table), gen::FIXTURE_REPORT);
+}
+
+/// A sample picks the same rows whatever the chunking, and the same as `produce_table_sampled`
+#[test]
+fn sample_is_the_same_across_thread_counts() {
+    let rows = generated(20_000, 7);
+    let file = TempFile::new("sampled", &rows);
+
+    let ingest = onebrc::IngestOptions::default().sample_rate(0.1, 99);
+    let one = onebrc::run(&file, &RunOptions::default().threads(1).ingest(ingest.clone())).unwrap();
+    let four = onebrc::run(&file, &RunOptions::default().threads(4).ingest(ingest)).unwrap();
+    let direct = onebrc::produce_table_sampled(std::io::BufReader::new(rows.as_bytes()), 0.1, 99);
+
+    let sampled: u32 = one.values().map(|s| s.count()).sum();
+    assert!((1800..2200).contains(&sampled), "{sampled} rows sampled");
+    for other in [&four, &direct] {
+        assert!(onebrc::diff_tables(&one, other).is_empty());
+    }
+    let other_seed = onebrc::produce_table_sampled(std::io::BufReader::new(rows.as_bytes()), 0.1, 100);
+    assert!(!onebrc::diff_tables(&one, &other_seed).is_empty());
+}
+
+/// `every` keeps exactly the rows whose index is a multiple of `n`, whatever the chunking
+#[test]
+fn every_nth_row_across_thread_counts() {
+    let rows = generated(1000, 106);
+    let file = TempFile::new("every", &rows);
+    for n in [2, 5, 7] {
+        let kept: String = rows.lines().step_by(n as usize).map(|row| format!("{row}\n")).collect();
+        let expected = onebrc::produce_table_bytes(kept.as_bytes());
+        for threads in [1, 3, 8] {
+            let options = RunOptions::default().threads(threads).ingest(onebrc::IngestOptions::default().every(n));
+            let table = onebrc::run(&file, &options).unwrap();
+            assert!(onebrc::diff_tables(&expected, &table).is_empty(), "every {n}, {threads} threads");
+        }
+    }
+}
+
+/// Every station is in exactly one shard, and together the shards are what `run` gives
+#[test]
+fn partitioned_run_keeps_stations_in_one_shard() {
+    let file = TempFile::new("partitioned", generated(20_000, 180));
+    let options = RunOptions::default().threads(3);
+    let shards = onebrc::run_partitioned(&file, &options, 4).unwrap();
+    let table = onebrc::run(&file, &options).unwrap();
+
+    assert_eq!(shards.len(), 4);
+    let mut seen = std::collections::HashSet::new();
+    for shard in &shards {
+        for name in shard.keys() {
+            assert!(seen.insert(name.clone()), "{} is in two shards", String::from_utf8_lossy(name));
+        }
+    }
+    assert!(onebrc::diff_tables(&table, &onebrc::merge_all(shards)).is_empty());
+}
+
+/// Streaming hands over every shard exactly once, each complete, and stops calling back after an
+/// error, which it returns
+#[test]
+fn streamed_shards_are_complete() {
+    let file = TempFile::new("streamed", generated(20_000, 192));
+    let options = RunOptions::default().threads(3);
+    let mut streamed = Vec::new();
+    onebrc::run_partitioned_streaming(&file, &options, 5, |shard, table| {
+        streamed.push((shard, table));
+        Ok(())
+    }).unwrap();
+    let mut calls = 0;
+    let failed = onebrc::run_partitioned_streaming(&file, &options, 5, |_, _| {
+        calls += 1;
+        Err(onebrc::Error::MissingNewline)
+    });
+    let table = onebrc::run(&file, &options).unwrap();
+
+    let mut numbers: Vec<_> = streamed.iter().map(|(shard, _)| *shard).collect();
+    numbers.sort_unstable();
+    assert_eq!(numbers, [0, 1, 2, 3, 4]);
+    for (shard, streamed) in &streamed {
+        assert_eq!(onebrc::shard_table(streamed.clone(), 5)[*shard].len(), streamed.len());
+    }
+    assert!(onebrc::diff_tables(&table, &onebrc::merge_all(streamed.into_iter().map(|(_, t)| t))).is_empty());
+    assert!(matches!(failed, Err(onebrc::Error::MissingNewline)));
+    assert_eq!(calls, 1);
+}
+
+/// Reading a file three times over triples the counts and sums, and leaves the min and max alone
+#[test]
+fn repeated_run_scales_counts() {
+    let file = TempFile::new("repeated", generated(3000, 11));
+    let options = RunOptions::default().threads(3);
+    let once = onebrc::run(&file, &options).unwrap();
+    let (thrice, stats) = onebrc::run_repeated(&file, &options, 3).unwrap();
+
+    assert_eq!(stats.len(), 9);
+    assert_eq!(stats.iter().map(|s| s.rows).sum::<u64>(), 9000);
+    assert_eq!(thrice.len(), once.len());
+    for (city, sample) in &once {
+        let repeated = &thrice[city];
+        assert_eq!(repeated.count(), 3 * sample.count());
+        assert_eq!(repeated.min(), sample.min());
+        assert_eq!(repeated.max(), sample.max());
+        assert!((repeated.mean() - sample.mean()).abs() < 0.001);
+    }
+}
+
+/// Keyed by id, the same rows give the same samples as keyed by name, and the report is in
+/// numeric order rather than the names' byte order
+#[test]
+fn int_ids_match_names() {
+    let rows: String = gen::gen_seeded(5000, 3)
+        .map(|(city, temp)| format!("{};{temp:.1}\n", city.len() * 37 % 1000))
+        .collect();
+    let by_id = onebrc::produce_table_int(std::io::BufReader::new(rows.as_bytes()), &Default::default()).unwrap();
+    let by_name = onebrc::produce_table_bytes(rows.as_bytes());
+    assert_eq!(by_id.len(), by_name.len());
+    for (id, sample) in &by_id {
+        let named = &by_name[id.to_string().as_bytes()];
+        assert_eq!((sample.count(), sample.min(), sample.max()), (named.count(), named.min(), named.max()), "{id}");
+    }
+
+    let mut report = Vec::new();
+    onebrc::report_int(&by_id, &mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    let ids: Vec<u32> = report.trim_matches(|c| c == '{' || c == '}' || c == '\n').split(", ")
+        .map(|entry| entry.split_once('=').unwrap().0.parse().unwrap())
+        .collect();
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(ids.len(), by_id.len());
+}
+
+/// With a station cap, the common stations come through exactly, and every other row ends up in
+/// `__OTHER__`, whether the file is read in one chunk or several
+#[test]
+fn max_stations_folds_rare_stations_into_other() {
+    // two common stations every other row, and a thousand rare ones in between
+    let rows: String = (0..4000)
+        .map(|i| match i % 4 {
+            0 => "Common;1.0\n".to_string(),
+            2 => "Frequent;2.0\n".to_string(),
+            _ => format!("rare{};{}.0\n", i % 1000, i % 7),
+        })
+        .collect();
+    let file = TempFile::new("max-stations", &rows);
+
+    let ingest = onebrc::IngestOptions::default().max_stations(10);
+    let direct = onebrc::produce_table_with(std::io::BufReader::new(rows.as_bytes()), &ingest).unwrap();
+    let tables = [1, 4].map(|threads| onebrc::run(&file, &RunOptions::default().threads(threads).ingest(ingest.clone())).unwrap());
+
+    for table in tables.iter().chain([&direct]) {
+        assert!(table.len() <= 11, "{} stations", table.len());
+        assert_eq!(table[b"Common".as_slice()].count(), 1000);
+        assert_eq!(table[b"Frequent".as_slice()].count(), 1000);
+        let other = &table[onebrc::OTHER_STATION];
+        let rare: u32 = table.iter()
+            .filter(|(name, _)| name.starts_with(b"rare"))
+            .map(|(_, sample)| sample.count())
+            .sum();
+        assert!(other.count() > 1000, "only {} rows evicted", other.count());
+        assert_eq!(other.count() + rare, 2000);
+        assert_eq!((other.min(), other.max()), (0.0, 6.0));
+    }
+}
+
+/// A `limit_rows` cap split between the chunks is exact when every chunk has its share of rows,
+/// and is never exceeded when some don't
+#[test]
+fn limit_rows_across_threads() {
+    let file = TempFile::new("limit-rows", generated(5000, 139));
+    let total = |table: &onebrc::Table| table.values().map(|s| s.count() as u64).sum::<u64>();
+    for threads in [1, 3, 8] {
+        for n in [0, 1, 7, 1000] {
+            let options = RunOptions::default().threads(threads).ingest(onebrc::IngestOptions::default().limit_rows(n));
+            assert_eq!(total(&onebrc::run(&file, &options).unwrap()), n, "{threads} threads, limit {n}");
+        }
+        // a cap past the end reads everything, and one just short of it can't be met by every chunk
+        for n in [4999, 5000, 1 << 40] {
+            let options = RunOptions::default().threads(threads).ingest(onebrc::IngestOptions::default().limit_rows(n));
+            let read = total(&onebrc::run(&file, &options).unwrap());
+            assert!(read <= n.min(5000), "{threads} threads, limit {n}: read {read}");
+            if threads == 1 || n > 5000 {
+                assert_eq!(read, n.min(5000), "{threads} threads, limit {n}");
+            }
+        }
+    }
+}
+
+/// A run that spills its partial tables to disk, many times over, gives the same table as one
+/// that doesn't, and cleans up after itself
+#[test]
+fn spilled_run_matches() {
+    let file = TempFile::new("spill", generated(20_000, 140));
+    let dir = std::env::temp_dir().join(format!("onebrc-spill-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for threads in [1, 4] {
+        let plain = onebrc::run(&file, &RunOptions::default().threads(threads)).unwrap();
+        let spilled = onebrc::run(&file, &RunOptions::default().threads(threads).spill_dir(&dir).spill_threshold(10)).unwrap();
+        assert!(onebrc::diff_tables(&spilled, &plain).is_empty(), "{threads} threads");
+    }
+    let left = fs::read_dir(&dir).unwrap().count();
+    let _ = fs::remove_dir(&dir);
+    assert_eq!(left, 0);
+}
+
+/// `run_dir` reads the files in a directory, skipping a subdirectory and a dangling symlink
+#[cfg(unix)]
+#[test]
+fn dir_skips_entries_that_arent_files() {
+    let dir = std::env::temp_dir().join(format!("onebrc-dir-{}", std::process::id()));
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join("part-00000"), "Oslo;1.0\nAbha;2.0\n").unwrap();
+    fs::write(dir.join("part-00001"), "Oslo;3.0\n").unwrap();
+    std::os::unix::fs::symlink(dir.join("gone"), dir.join("part-00002")).unwrap();
+    let table = onebrc::run_dir(&dir, &RunOptions::default().threads(2));
+    let _ = fs::remove_dir_all(&dir);
+    assert_eq!(onebrc::report_to_string(&table.unwrap()), "{Abha=2.0/2.0/2.0, Oslo=1.0/2.0/3.0}\n");
+}
+
+/// A reader that panics once it's been read from, as a stand-in for a worker that dies partway
+struct Panicking;
+
+impl std::io::Read for Panicking {
+    fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+        panic!("injected panic");
+    }
+}
+
+/// A worker that panics fails the run with the worker's number, rather than the others' tables
+/// being reported as if they were everything, and so does a panic in the partials' hook
+#[test]
+fn worker_panic_fails_the_run() {
+    let streams: Vec<Box<dyn std::io::Read + Send>> = vec![
+        Box::new(b"Oslo;1.0\n".as_slice()),
+        Box::new(Panicking),
+        Box::new(b"Abha;2.0\n".as_slice()),
+    ];
+    let result = onebrc::run_streams(streams, &RunOptions::default());
+    assert!(matches!(result, Err(onebrc::Error::WorkerPanicked(1))), "{result:?}");
+
+    let file = TempFile::new("panic", generated(1000, 145));
+    for reducers in [1, 3] {
+        let options = RunOptions::default().threads(4).reducers(reducers);
+        let mut partials = 0;
+        let result = onebrc::run_with_hook(&file, &options, |_| {
+            partials += 1;
+            if partials == 2 {
+                panic!("injected panic");
+            }
+        });
+        assert!(matches!(result, Err(onebrc::Error::WorkerPanicked(_))), "{reducers} reducers: {result:?}");
+    }
+}
+
+/// The second pass's variances, against ones known exactly, and a two-pass f64 reference over
+/// generated rows
+#[test]
+fn variance_of_known_data() {
+    let rows: String = ["A;1.0", "A;2.0", "B;10.0", "A;3.0", "A;4.0", "C;-5.0", "C;5.0"].iter().map(|r| format!("{r}\n")).collect();
+    let file = TempFile::new("variance", &rows);
+    for threads in [1, 3] {
+        let options = RunOptions::default().threads(threads);
+        let table = onebrc::run(&file, &options).unwrap();
+        let variance = onebrc::run_variance(&file, &options, &table).unwrap();
+        assert_eq!(variance.len(), 3);
+        assert_eq!(variance[b"A".as_slice()], 1.25);
+        assert_eq!(variance[b"B".as_slice()], 0.0);
+        assert_eq!(variance[b"C".as_slice()], 25.0);
+    }
+
+    // and generated rows, against the textbook two-pass formula over the same f32 values
+    let rows = generated(20_000, 150);
+    let file = TempFile::new("variance-generated", &rows);
+    let mut values: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+    for row in rows.lines() {
+        let (city, temp) = row.split_once(';').unwrap();
+        values.entry(city).or_default().push(temp.parse::<f32>().unwrap() as f64);
+    }
+    let options = RunOptions::default().threads(4);
+    let variance = onebrc::run_variance(&file, &options, &onebrc::run(&file, &options).unwrap()).unwrap();
+    assert_eq!(variance.len(), values.len());
+    for (city, values) in values {
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let expected = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / values.len() as f64;
+        let got = variance[city.as_bytes()];
+        assert!((got - expected).abs() <= 1e-9 * expected.max(1.0), "{city}: {got} vs {expected}");
+    }
+}
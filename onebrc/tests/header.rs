@@ -0,0 +1,37 @@
+use std::io::BufReader;
+
+use onebrc::IngestOptions;
+
+const WITH_HEADER: &[u8] = b"# sep=| order=temp,name\n12.5|Hamburg\n-3.0|Oslo\n4.5|Hamburg\n";
+const WITHOUT_HEADER: &[u8] = b"A rather long station name;12.5\nOslo;-3.0\n";
+
+/// A header is recognised however the pages split it, even when it's longer than a page
+#[test]
+fn header_across_pages() {
+    for capacity in [4, 1, 7, 100] {
+        let (table, stats) = onebrc::produce_table_with_stats(BufReader::with_capacity(capacity, WITH_HEADER), &IngestOptions::default())
+            .unwrap_or_else(|e| panic!("{e} with {capacity}-byte pages"));
+        assert_eq!(table.len(), 2, "{capacity}-byte pages");
+        let hamburg = &table[b"Hamburg".as_slice()];
+        assert_eq!((hamburg.count(), hamburg.min(), hamburg.max()), (2, 4.5, 12.5), "{capacity}-byte pages");
+        assert_eq!(table[b"Oslo".as_slice()].min(), -3.0, "{capacity}-byte pages");
+        assert_eq!(stats.bad_lines, 0);
+    }
+    // the header isn't a row, for a limit
+    let options = IngestOptions::default().limit_rows(1);
+    let table = onebrc::produce_table_with(BufReader::with_capacity(4, WITH_HEADER), &options).unwrap();
+    assert_eq!(table[b"Hamburg".as_slice()].count(), 1);
+    let options = IngestOptions::default().limit_rows(0);
+    assert!(onebrc::produce_table_with(BufReader::with_capacity(4, WITH_HEADER), &options).unwrap().is_empty());
+}
+
+/// A first row that isn't a header comes through whole, however the pages split it
+#[test]
+fn long_first_row() {
+    for capacity in [4, 1, 7, 100] {
+        let table = onebrc::produce_table_with(BufReader::with_capacity(capacity, WITHOUT_HEADER), &IngestOptions::default())
+            .unwrap_or_else(|e| panic!("{e} with {capacity}-byte pages"));
+        assert_eq!(table[b"A rather long station name".as_slice()].max(), 12.5, "{capacity}-byte pages");
+        assert_eq!(table[b"Oslo".as_slice()].max(), -3.0, "{capacity}-byte pages");
+    }
+}
@@ -0,0 +1,18 @@
+use onebrc::hist::HistSample;
+
+/// Values outside -99.9..=99.9, infinite or NaN are only counted, at either end of the range
+#[test]
+fn out_of_range_values() {
+    let mut hist = HistSample::default();
+    for v in [f32::INFINITY, f32::NEG_INFINITY, f32::NAN, 1e30, -1e30, 100.0, -99.96, f32::MAX] {
+        hist.add(v);
+    }
+    assert_eq!((hist.count(), hist.out_of_range()), (0, 8));
+    assert!(hist.mode().is_nan());
+
+    for v in [99.9, -99.9, 99.94, -99.94] {
+        hist.add(v);
+    }
+    assert_eq!((hist.count(), hist.out_of_range()), (4, 8));
+    assert_eq!(hist.mode(), -99.9);
+}
@@ -0,0 +1,128 @@
+use std::io::{BufReader, Cursor, Read};
+
+use onebrc::{Error, ParseError};
+
+const ROWS: &[u8] = b"Hamburg;12.0\nBulawayo;8.9\nPalembang;38.8\nHamburg;-3.4\nSt. John's;15.2\nBulawayo;-1.1\n";
+
+fn readers(parts: &[&[u8]]) -> Vec<Box<dyn Read>> {
+    parts.iter().map(|part| Box::new(Cursor::new(part.to_vec())) as Box<dyn Read>).collect()
+}
+
+/// Known Fahrenheit and Celsius pairs: the values are converted as they're read, then rounded to
+/// the report's one decimal place
+#[test]
+fn fahrenheit_pairs() {
+    let input = b"Boiling;212\nFreezing;32\nCrossover;-40\nBody;98.6\nZero;0\n";
+    let options = onebrc::IngestOptions::default().fahrenheit(true);
+    let table = onebrc::produce_table_with(BufReader::new(input.as_slice()), &options).unwrap();
+    let pairs = [("Boiling", "100.0"), ("Freezing", "0.0"), ("Crossover", "-40.0"), ("Body", "37.0"), ("Zero", "-17.8")];
+    for (name, celsius) in pairs {
+        assert_eq!(format!("{:.1}", table[name.as_bytes()].mean()), celsius, "{name}");
+    }
+}
+
+/// A row whose separator is at the end of one reader and whose value and newline are in the next
+#[test]
+fn chained_row_split_after_separator() {
+    let table = onebrc::produce_table_chained(readers(&[b"Oslo;1.0\nHamburg;", b"12.0\nOslo;3.0\n"]));
+    assert_eq!(table.len(), 2);
+    let hamburg = &table[b"Hamburg".as_slice()];
+    assert_eq!((hamburg.min(), hamburg.max(), hamburg.count()), (12.0, 12.0, 1));
+    let oslo = &table[b"Oslo".as_slice()];
+    assert_eq!((oslo.min(), oslo.max(), oslo.count()), (1.0, 3.0, 2));
+}
+
+/// However the input is cut between two or three readers, including empty ones, it reads the same
+/// as the whole
+#[test]
+fn chained_at_every_split() {
+    let whole = onebrc::produce_table(BufReader::new(ROWS));
+    for i in 0..=ROWS.len() {
+        let table = onebrc::produce_table_chained(readers(&[&ROWS[..i], &ROWS[i..]]));
+        assert!(onebrc::diff_tables(&table, &whole).is_empty(), "split at {i}");
+        for j in i..=ROWS.len() {
+            let table = onebrc::produce_table_chained(readers(&[&ROWS[..i], &ROWS[i..j], b"", &ROWS[j..]]));
+            assert!(onebrc::diff_tables(&table, &whole).is_empty(), "split at {i} and {j}");
+        }
+    }
+    assert!(onebrc::produce_table_chained(Vec::new()).is_empty());
+}
+
+/// With `cr_line_endings`, `\r` ends each row, whether or not the pages split the rows
+#[test]
+fn carriage_return_rows() {
+    let cr: Vec<u8> = ROWS.iter().map(|&b| if b == b'\n' { b'\r' } else { b }).collect();
+    let options = onebrc::IngestOptions::default().cr_line_endings(true);
+    let whole = onebrc::produce_table(BufReader::new(ROWS));
+    for capacity in [1, 5, 64, 8192] {
+        let table = onebrc::produce_table_with(BufReader::with_capacity(capacity, cr.as_slice()), &options).unwrap();
+        assert!(onebrc::diff_tables(&table, &whole).is_empty(), "{capacity}-byte pages");
+    }
+    // without it, the whole input is one row that never ends
+    assert!(onebrc::produce_table_with(BufReader::new(cr.as_slice()), &Default::default()).is_err());
+}
+
+/// `\r\n` endings aren't supported: by default the `\r` ends up in the value, which then isn't a
+/// number, so a Windows-style file fails rather than being misread
+#[test]
+fn crlf_rows_are_rejected() {
+    let crlf = b"Oslo;1.0\r\nAbha;2.0\r\n";
+    let result = onebrc::produce_table_with(BufReader::new(crlf.as_slice()), &Default::default());
+    assert!(matches!(result, Err(Error::Parse(ParseError::BadCharacter(b'\r')))));
+}
+
+/// A split point in the last row moves forward to the end of the input, and is dropped rather
+/// than making an empty last chunk
+#[test]
+fn chunk_boundary_at_end_of_input() {
+    let rows = b"a;1.0\nthe last row is the longest;2.0\n";
+    // the only split point, at half way, is in the last row
+    assert_eq!(onebrc::chunk_boundaries(&mut Cursor::new(rows), 2, b'\n').unwrap(), [0, rows.len() as u64]);
+    // a split point on the last newline, so that the next row would start exactly at the end
+    let rows = b"ab;1\ncd;2\n";
+    assert_eq!(onebrc::chunk_boundaries(&mut Cursor::new(rows), 10, b'\n').unwrap(), [0, 5, 10]);
+    assert_eq!(onebrc::chunk_boundaries(&mut Cursor::new(b""), 4, b'\n').unwrap(), [0, 0]);
+
+    // whatever the count, the chunks are non-empty, start at rows and cover the input
+    for n in 1..=ROWS.len() + 2 {
+        let bounds = onebrc::chunk_boundaries(&mut Cursor::new(ROWS), n, b'\n').unwrap();
+        assert_eq!((bounds[0], *bounds.last().unwrap()), (0, ROWS.len() as u64), "{n} chunks");
+        assert!(bounds.len() <= n + 1, "{n} chunks");
+        assert!(bounds.windows(2).all(|w| w[0] < w[1]), "{n} chunks: {bounds:?}");
+        assert!(bounds[1..bounds.len() - 1].iter().all(|&b| ROWS[b as usize - 1] == b'\n'), "{n} chunks: {bounds:?}");
+    }
+}
+
+/// European-style input: fields separated by `;`, and `,` as the decimal point
+#[test]
+fn comma_decimals() {
+    let options = onebrc::IngestOptions::default().decimal_point(b',');
+    let input = b"Hamburg;12,0\nBulawayo;8,9\nHamburg;-3,4\nSt. John's;15,2\n";
+    let table = onebrc::produce_table_with(BufReader::new(input.as_slice()), &options).unwrap();
+    assert_eq!(onebrc::report_to_string(&table), "{Bulawayo=8.9/8.9/8.9, Hamburg=-3.4/4.3/12.0, St. John's=15.2/15.2/15.2}\n");
+    // a `.` is then just a bad character
+    let result = onebrc::produce_table_with(BufReader::new(b"Hamburg;12.0\n".as_slice()), &options);
+    assert!(matches!(result, Err(Error::Parse(ParseError::BadCharacter(b'.')))));
+
+    // the two are independent, even both `,`: a row splits at its first separator
+    let options = options.separator(b',');
+    let table = onebrc::produce_table_with(BufReader::new(b"Hamburg,12,5\nHamburg,-3\n".as_slice()), &options).unwrap();
+    assert_eq!(onebrc::report_to_string(&table), "{Hamburg=-3.0/4.8/12.5}\n");
+}
+
+/// However many threads, the parallel version gives the same table as the single-threaded one,
+/// including when a header changes the separator
+#[test]
+fn parallel_matches_single_threaded() {
+    let many: Vec<u8> = (0..5000).flat_map(|i| format!("Station{};{}.{}\n", i % 37, i % 199 - 99, i % 10).into_bytes()).collect();
+    let header: Vec<u8> = [b"# sep=|\n".as_slice(), &many.iter().map(|&b| if b == b';' { b'|' } else { b }).collect::<Vec<_>>()].concat();
+    for input in [ROWS, b"", &many, &header] {
+        let single = onebrc::produce_table_bytes(input);
+        for threads in [1, 2, 3, 4, 7, 16, 64] {
+            let parallel = onebrc::produce_table_parallel(input, threads);
+            assert!(onebrc::diff_tables(&parallel, &single).is_empty(), "{threads} threads");
+        }
+    }
+    let with_header = onebrc::produce_table_bytes(&header);
+    assert!(onebrc::diff_tables(&with_header, &onebrc::produce_table_bytes(&many)).is_empty());
+}
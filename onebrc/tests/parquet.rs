@@ -0,0 +1,34 @@
+#![cfg(feature = "parquet")]
+
+use onebrc::{ReportFormat, ReportOptions};
+
+fn parquet(input: &[u8], options: ReportOptions) -> Vec<u8> {
+    let table = onebrc::produce_table_bytes(input);
+    let mut out = Vec::new();
+    onebrc::report_with(&table, &options.format(ReportFormat::Parquet), &mut out).unwrap();
+    out
+}
+
+/// `tests/data/small.parquet` is the report of two stations with data and two without, checked
+/// with a separate decoder written from the format's specification: the schema, a row group of
+/// five column chunks, and each page's levels and values.
+#[test]
+fn matches_checked_file() {
+    let known = ["Abha", "Nowhere", "Oslo", "Zzz"].map(|s| s.as_bytes().to_vec());
+    let options = ReportOptions::default().known_stations(known).show_missing(true);
+    let file = parquet(b"Abha;1.0\nOslo;-2.5\nOslo;3.0\n", options);
+    assert_eq!(file, std::fs::read("tests/data/small.parquet").unwrap());
+}
+
+/// Whatever the entries, the file starts and ends with the magic, and the footer's length fits
+#[test]
+fn framing() {
+    let many: String = (0..1000).map(|i| format!("Station{i};{}.5\n", i % 100)).collect();
+    for input in [b"".as_slice(), b"Abha;1.0\n", many.as_bytes()] {
+        let file = parquet(input, ReportOptions::default());
+        assert_eq!(&file[..4], b"PAR1");
+        assert_eq!(&file[file.len() - 4..], b"PAR1");
+        let footer = u32::from_le_bytes(file[file.len() - 8..file.len() - 4].try_into().unwrap()) as usize;
+        assert!(footer + 12 <= file.len());
+    }
+}
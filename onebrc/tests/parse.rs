@@ -0,0 +1,160 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Whether `parse_decimal` agrees with the standard library's parse of the same text, to within
+/// the rounding of its integer-then-divide approach (a couple of ulps).
+fn check_parse(bs: &[u8]) -> bool {
+    let fast = onebrc::parse_decimal(bs);
+    let std: f32 = std::str::from_utf8(bs).unwrap().parse().unwrap();
+    (fast - std).abs() <= 2.0 * f32::EPSILON * std.abs().max(f32::MIN_POSITIVE)
+}
+
+/// Every value the challenge's input can hold: -99.9 to 99.9, one decimal place
+#[test]
+fn every_temperature() {
+    for tenths in -999i32..=999 {
+        let text = format!("{}{}.{}", if tenths < 0 { "-" } else { "" }, tenths.abs() / 10, tenths.abs() % 10);
+        assert!(check_parse(text.as_bytes()), "{text}");
+    }
+}
+
+#[test]
+fn edge_cases() {
+    for text in ["0.0", "-0.0", "+0.0", "0", "-0", "99.9", "-99.9", "100", "0.001", "007.50", "-.5", "5."] {
+        assert!(check_parse(text.as_bytes()), "{text}");
+    }
+}
+
+/// Random decimals of other shapes: optional sign, up to five integer digits (with leading zeros),
+/// and up to three decimal places, or none and no point at all. Seeded, so a failure reproduces.
+#[test]
+fn random_decimals() {
+    let mut rng = StdRng::seed_from_u64(154);
+    for _ in 0..100_000 {
+        let mut text = String::from(["", "-", "+"][rng.gen_range(0..3)]);
+        for _ in 0..rng.gen_range(1..=5) {
+            text.push(char::from(b'0' + rng.gen_range(0..10)));
+        }
+        let places = rng.gen_range(0..=3);
+        if places > 0 || rng.gen_bool(0.5) {
+            text.push('.');
+        }
+        for _ in 0..places {
+            text.push(char::from(b'0' + rng.gen_range(0..10)));
+        }
+        assert!(check_parse(text.as_bytes()), "{text}");
+    }
+}
+
+/// The value of a one-row table read with `options`
+fn read_one(row: &str, options: &onebrc::IngestOptions) -> Result<f32, onebrc::Error> {
+    let table = onebrc::produce_table_with(std::io::BufReader::new(row.as_bytes()), options)?;
+    Ok(table[b"S".as_slice()].min())
+}
+
+/// More digits than the `i32` they're gathered in can hold is an error, rather than a value that
+/// has silently wrapped around
+#[test]
+fn over_long_values_overflow() {
+    use onebrc::{Error, ParseError};
+
+    let options = onebrc::IngestOptions::default();
+    for row in ["S;99999999999.9\n", "S;214748364.8\n", "S;12345678901\n"] {
+        assert!(matches!(read_one(row, &options), Err(Error::Parse(ParseError::Overflow))), "{row:?}");
+    }
+    // the most that fits
+    assert_eq!(read_one("S;214748364.7\n", &options).unwrap(), 214748364.7);
+}
+
+/// The sign is read once, before the digits, and applied at the end: a negative is exactly the
+/// negation of its digits' value, and a sign anywhere else is a bad character
+#[test]
+fn sign_applied_once() {
+    use onebrc::{Error, ParseError};
+
+    let options = onebrc::IngestOptions::default();
+    let value = |text: &str| read_one(&format!("S;{text}\n"), &options);
+    for text in ["0.1", "12.3", "99.9", "7", "007.50", "12.3456", "16777.00098", "2147483.647"] {
+        let positive = value(text).unwrap();
+        assert_eq!(value(&format!("-{text}")).unwrap().to_bits(), (-positive).to_bits(), "{text}");
+        assert_eq!(value(&format!("+{text}")).unwrap(), positive, "{text}");
+    }
+    for text in ["-", "+", "-.", "+."] {
+        assert!(matches!(value(text), Err(Error::Parse(ParseError::Empty))), "{text}");
+    }
+    for (text, b) in [("--5", b'-'), ("+-5", b'-'), ("-+5", b'+'), ("++5", b'+'), ("5-", b'-'), ("1.-2", b'-')] {
+        assert!(matches!(value(text), Err(Error::Parse(ParseError::BadCharacter(c))) if c == b), "{text}");
+    }
+}
+
+#[test]
+fn scientific_notation() {
+    let options = onebrc::IngestOptions::default().scientific(true);
+    assert_eq!(read_one("S;1.2e1\n", &options).unwrap(), 12.0);
+    assert_eq!(read_one("S;-3.0e-1\n", &options).unwrap(), -0.3);
+    assert_eq!(read_one("S;5E+0\n", &options).unwrap(), 5.0);
+    let comma = options.clone().decimal_point(b',');
+    assert_eq!(read_one("S;1,25e2\n", &comma).unwrap(), 125.0);
+    for bad in ["S;1.2e\n", "S;e5\n", "S;1.2e1.5\n", "S;1.2ex\n", "S;inf\n"] {
+        assert!(read_one(bad, &options).is_err(), "{bad:?}");
+    }
+    // off by default
+    assert!(read_one("S;1.2e1\n", &Default::default()).is_err());
+}
+
+/// With scientific notation on, ordinary values still go through the fast parser: this one has
+/// too many digits for an f32, and the fast parser rounds it differently from the standard library
+#[test]
+fn scientific_keeps_the_fast_path() {
+    let options = onebrc::IngestOptions::default().scientific(true);
+    let std: f32 = "16777.00098".parse().unwrap();
+    assert_ne!(onebrc::parse_decimal(b"16777.00098"), std);
+    assert_eq!(read_one("S;16777.00098\n", &options).unwrap(), onebrc::parse_decimal(b"16777.00098"));
+}
+
+/// More than the one decimal place the challenge uses: these take the `powi` branch, and should
+/// still give the nearest `f32`, the same as the standard library's parse
+#[test]
+fn more_decimal_places() {
+    assert_eq!(onebrc::parse_decimal(b"12.34"), 12.34);
+    assert_eq!(onebrc::parse_decimal(b"12.345"), 12.345);
+    assert_eq!(onebrc::parse_decimal(b"12.3456"), 12.3456);
+    assert_eq!(onebrc::parse_decimal(b"-12.3456"), -12.3456);
+    assert_eq!(onebrc::parse_decimal(b"0.0000001"), 1e-7);
+    assert_eq!(onebrc::parse_decimal(b"1.000000001"), 1.0);
+}
+
+/// The precision model `parse_decimal_checked` documents: when the digits, read as an integer, are
+/// below 2^24, the result is exactly the `f32` nearest the text, however many places there are
+#[test]
+fn exact_below_2_pow_24() {
+    let mut rng = StdRng::seed_from_u64(199);
+    for _ in 0..200_000 {
+        let digits = rng.gen_range(0..1u32 << 24).to_string();
+        let places = rng.gen_range(0..=digits.len());
+        let (int, frac) = digits.split_at(digits.len() - places);
+        let text = format!("{}{int}.{frac}", ["", "-"][rng.gen_range(0..2)]);
+        let std: f32 = text.parse().unwrap();
+        assert_eq!(onebrc::parse_decimal(text.as_bytes()), std, "{text}");
+    }
+}
+
+/// Only one decimal point: a second one is where the number stops making sense
+#[test]
+fn second_point_is_rejected() {
+    use onebrc::{parse_decimal_checked, ParseError};
+
+    for text in ["1.2.3", "1..2", "..5", "-1.2.", "5.."] {
+        assert_eq!(parse_decimal_checked(text.as_bytes()), Err(ParseError::BadCharacter(b'.')), "{text}");
+    }
+    let comma = onebrc::IngestOptions::default().decimal_point(b',');
+    assert_eq!(parse_decimal_checked(b"1.2,3"), Err(ParseError::BadCharacter(b',')));
+    assert!(matches!(read_one("S;1,2,3\n", &comma), Err(onebrc::Error::Parse(ParseError::BadCharacter(b',')))));
+
+    // so skipping bad lines drops them, rather than reading them as some other number
+    let skip = onebrc::IngestOptions::default().skip_bad_lines(true);
+    let input = b"S;1.2.3\nS;1..2\nS;4.5\n";
+    let (table, stats) = onebrc::produce_table_with_stats(std::io::BufReader::new(input.as_slice()), &skip).unwrap();
+    assert_eq!(stats.bad_lines, 2);
+    assert_eq!(table[b"S".as_slice()].count(), 1);
+}
@@ -0,0 +1,90 @@
+use onebrc::{ReportOptions, Rounding};
+
+/// The report of `input` with `options`
+fn report(input: &[u8], options: &ReportOptions) -> String {
+    let table = onebrc::produce_table(std::io::BufReader::new(input));
+    let mut out = Vec::new();
+    onebrc::report_with(&table, options, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// Stations that aren't in the registry are left out; registry stations without data are left out
+/// too, unless `show_missing` lists them
+#[test]
+fn known_stations() {
+    let registry = onebrc::read_station_list(b"Oslo\n\nAbha\nNowhere\n".as_slice()).unwrap();
+    assert_eq!(registry, [b"Oslo".to_vec(), b"Abha".to_vec(), b"Nowhere".to_vec()]);
+    let input = b"Oslo;1.0\nRogue;5.0\nAbha;2.0\nOslo;3.0\n";
+    let options = ReportOptions::default().known_stations(registry);
+    assert_eq!(report(input, &options), "{Abha=2.0/2.0/2.0, Oslo=1.0/2.0/3.0}\n");
+    assert_eq!(report(input, &options.clone().show_missing(true)), "{Abha=2.0/2.0/2.0, Nowhere=MISSING, Oslo=1.0/2.0/3.0}\n");
+    // without a registry, everything is reported, and there's nothing to be missing
+    assert_eq!(report(input, &ReportOptions::default().show_missing(true)), "{Abha=2.0/2.0/2.0, Oslo=1.0/2.0/3.0, Rogue=5.0/5.0/5.0}\n");
+}
+
+/// Names whose invalid bytes both decode to U+FFFD print the same, and are ordered by their raw
+/// bytes, whichever order the table holds them in
+#[test]
+fn equal_decoded_names_order_by_raw_bytes() {
+    let expected = "{A\u{FFFD}=1.0/1.0/1.0, A\u{FFFD}=2.0/2.0/2.0, B=0.0/0.0/0.0}\n";
+    for _ in 0..20 {
+        for input in [b"A\xfe;1.0\nA\xff;2.0\nB;0.0\n", b"A\xff;2.0\nB;0.0\nA\xfe;1.0\n"] {
+            assert_eq!(report(input, &ReportOptions::default()), expected);
+        }
+    }
+    // and the same with a missing station sorted in among them
+    let known = [b"A\xfe".to_vec(), b"A\xff".to_vec(), b"B".to_vec()];
+    let options = ReportOptions::default().known_stations(known).show_missing(true);
+    assert_eq!(report(b"A\xff;2.0\nB;0.0\n", &options), "{A\u{FFFD}=MISSING, A\u{FFFD}=2.0/2.0/2.0, B=0.0/0.0/0.0}\n");
+}
+
+/// `check_report` accepts a report of ours with equal decoded names side by side, but not names
+/// out of order
+#[test]
+fn check_report_allows_equal_names() {
+    let ours = report(b"A\xfe;1.0\nA\xff;2.0\nB;0.0\n", &ReportOptions::default());
+    assert_eq!(onebrc::check_report(&ours), Ok(3));
+    assert!(onebrc::check_report("{B=0.0/0.0/0.0, A=1.0/1.0/1.0}").is_err());
+}
+
+/// A station whose mean is printed as exactly the threshold isn't above it, even where the `f32`
+/// nearest the threshold is below the printed value
+#[test]
+fn mean_above_excludes_a_printed_equal() {
+    let input = b"Hot;20.3\nWarm;20.2\nHotter;20.4\n";
+    let options = ReportOptions::default().mean_above(20.3);
+    assert_eq!(report(input, &options), "{Hotter=20.4/20.4/20.4}\n");
+    assert_eq!(report(input, &ReportOptions::default().mean_above(20.25)), "{Hot=20.3/20.3/20.3, Hotter=20.4/20.4/20.4}\n");
+}
+
+/// Kelvin adds 273.15 to each unrounded Celsius value, then rounds once
+#[test]
+fn kelvin_conversion_and_rounding() {
+    let input = b"Zero;0.0\nAbsolute;-273.1\nWarm;20.3\nWarm;20.4\n";
+    let options = ReportOptions::default().kelvin(true);
+    // 273.15 is a tie, rounded half up. Warm's max is the f32 20.4, a little below 20.4, so it
+    // comes to a little below 293.55 and rounds down: not the printed 20.4 plus 273.15, rounded.
+    assert_eq!(report(input, &options), "{Absolute=0.0/0.0/0.0, Warm=293.4/293.5/293.5, Zero=273.2/273.2/273.2}\n");
+    assert_eq!(report(b"Zero;0.0\n", &options.clone().rounding(Rounding::HalfEven)), "{Zero=273.2/273.2/273.2}\n");
+    assert_eq!(report(b"Zero;0.0\n", &options.clone().rounding(Rounding::Floor)), "{Zero=273.1/273.1/273.1}\n");
+    // the threshold is in Kelvin too
+    assert_eq!(report(input, &options.mean_above(273.2)), "{Warm=293.4/293.5/293.5}\n");
+}
+
+/// A sum past 2^24 survives writing a counted report and reading it back, to the tenth
+#[test]
+fn counted_report_keeps_a_large_sum() {
+    let mut table = onebrc::Table::default();
+    table.insert(b"Busy".to_vec(), onebrc::Sample::new(-10.0, 99.9, 123456789.3, 2_000_000));
+    let options = ReportOptions::default().counts(true);
+    let mut out = Vec::new();
+    onebrc::report_with(&table, &options, &mut out).unwrap();
+    let report = String::from_utf8(out).unwrap();
+    assert_eq!(report, "{Busy=-10.0/61.7/99.9/2000000/123456789.3}\n");
+
+    let read = onebrc::read_counted_report(&report).unwrap();
+    assert_eq!(read[b"Busy".as_slice()].mean_f64(), 123456789.3 / 2_000_000.0);
+    let mut again = Vec::new();
+    onebrc::report_with(&read, &options, &mut again).unwrap();
+    assert_eq!(String::from_utf8(again).unwrap(), report);
+}
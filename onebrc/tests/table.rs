@@ -0,0 +1,87 @@
+use std::io::BufReader;
+
+use onebrc::{Error, IngestOptions, NonFinitePolicy, Sample, Table};
+
+fn table(rows: &[u8]) -> Table {
+    onebrc::produce_table(BufReader::new(rows))
+}
+
+/// No partials merge to an empty table, one merges to itself, and many to the table of all their
+/// rows together
+#[test]
+fn merge_all_partials() {
+    assert!(onebrc::merge_all(Vec::new()).is_empty());
+
+    let one = table(b"Oslo;1.0\nAbha;2.0\n");
+    assert!(onebrc::diff_tables(&onebrc::merge_all([one.clone()]), &one).is_empty());
+
+    let parts: [&[u8]; 4] = [b"Oslo;1.0\nAbha;2.0\n", b"", b"Oslo;-4.5\nCairo;30.0\n", b"Abha;7.0\nOslo;3.0\n"];
+    let merged = onebrc::merge_all(parts.map(table));
+    let whole = table(&parts.concat());
+    assert!(onebrc::diff_tables(&merged, &whole).is_empty());
+    assert_eq!(onebrc::report_to_string(&merged), "{Abha=2.0/4.5/7.0, Cairo=30.0/30.0/30.0, Oslo=-4.5/-0.2/3.0}\n");
+}
+
+/// NaN added to a sample leaves min and max alone but poisons the mean, which is why ingest
+/// doesn't let it through
+#[test]
+fn nan_in_a_sample() {
+    let mut sample = Sample::from(1.0);
+    sample.add(f32::NAN);
+    sample.add(3.0);
+    assert_eq!((sample.min(), sample.max(), sample.count()), (1.0, 3.0, 3));
+    assert!(sample.mean().is_nan());
+}
+
+/// A value that parses to infinity is rejected by default, or left out and counted with
+/// `NonFinitePolicy::Skip`
+#[test]
+fn non_finite_policy() {
+    let input = b"Hot;1e39\nHot;20.5\nCold;-1e39\nCold;-3.0\n";
+    let options = IngestOptions::default().scientific(true);
+    let result = onebrc::produce_table_with(BufReader::new(input.as_slice()), &options);
+    assert!(matches!(result, Err(Error::NonFinite)));
+
+    let skip = options.non_finite(NonFinitePolicy::Skip);
+    let (table, stats) = onebrc::produce_table_with_stats(BufReader::new(input.as_slice()), &skip).unwrap();
+    assert_eq!(stats.non_finite, 2);
+    assert_eq!(onebrc::report_to_string(&table), "{Cold=-3.0/-3.0/-3.0, Hot=20.5/20.5/20.5}\n");
+    // finite rows are untouched by the policy
+    let (_, stats) = onebrc::produce_table_with_stats(BufReader::new(b"Hot;1e38\n".as_slice()), &skip).unwrap();
+    assert_eq!(stats.non_finite, 0);
+}
+
+/// An empty sample that finds its way into a table is left out of the report, so its sentinels
+/// never print
+#[test]
+fn empty_sample_is_skipped() {
+    assert!(Sample::default().is_empty());
+    assert!(!Sample::from(0.0).is_empty());
+
+    let mut table = table(b"Oslo;1.0\n");
+    table.insert(b"Empty".to_vec(), Sample::default());
+    assert_eq!(onebrc::report_to_string(&table), "{Oslo=1.0/1.0/1.0}\n");
+    table.remove(b"Oslo".as_slice());
+    assert_eq!(onebrc::report_to_string(&table), "{}\n");
+}
+
+/// Stations with no more than the threshold's rows are dropped; the others are untouched
+#[test]
+fn retain_stations_by_count() {
+    let mut table = table(b"Oslo;1.0\nAbha;2.0\nOslo;3.0\nCairo;30.0\nAbha;7.0\nOslo;-4.5\n");
+    onebrc::retain_stations(&mut table, |_, sample| sample.count() > 1);
+    assert_eq!(onebrc::report_to_string(&table), "{Abha=2.0/4.5/7.0, Oslo=-4.5/-0.2/3.0}\n");
+    onebrc::retain_stations(&mut table, |name, _| name != b"Oslo");
+    assert_eq!(onebrc::report_to_string(&table), "{Abha=2.0/4.5/7.0}\n");
+    onebrc::retain_stations(&mut table, |_, sample| sample.count() > 2);
+    assert!(table.is_empty());
+}
+
+/// A changed station prints its values rounded as the report rounds them, half up, where `{:.1}`
+/// would round the tie in 12.25 down
+#[test]
+fn station_diff_rounds_like_the_report() {
+    let diffs = onebrc::diff_tables(&table(b"Oslo;12.0\nOslo;12.5\n"), &table(b"Oslo;13.0\n"));
+    let printed: Vec<_> = diffs.iter().map(|d| d.to_string()).collect();
+    assert_eq!(printed, ["Oslo: 12.0/12.3/12.5 (2 rows) vs 13.0/13.0/13.0 (1 rows)"]);
+}
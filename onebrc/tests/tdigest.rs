@@ -0,0 +1,61 @@
+#![cfg(feature = "tdigest")]
+
+use onebrc::tdigest::TDigest;
+
+const N: u32 = 100_000;
+
+/// The values 0 to N - 1, in the order of multiples of `step` (a prime other than 2 or 5, so every
+/// value comes once), so the true quantile of a value `x` is `x / N`
+fn scattered(step: u64) -> Vec<f64> {
+    (0..N as u64).map(|i| (i * step % N as u64) as f64).collect()
+}
+
+/// How far the estimate for each `q` is from `q`, in rank, given the true quantile of a value
+fn worst_rank_error(digest: &TDigest, rank: impl Fn(f64) -> f64) -> f64 {
+    (1..1000).map(|i| i as f64 / 1000.0)
+        .map(|q| (rank(digest.quantile(q).unwrap()) - q).abs())
+        .fold(0.0, f64::max)
+}
+
+/// On a uniform distribution, and a skewed one, added in a scattered order, every estimate is
+/// within 0.002 of its rank, and the ends are exact
+#[test]
+fn known_distributions() {
+    let mut uniform = TDigest::default();
+    let mut squares = TDigest::default();
+    for v in scattered(7919) {
+        uniform.add(v);
+        squares.add(v * v);
+    }
+    assert_eq!(uniform.count(), N as f64);
+    let n = N as f64;
+    assert!(worst_rank_error(&uniform, |x| x / n) < 0.002);
+    assert!(worst_rank_error(&squares, |x| x.sqrt() / n) < 0.002);
+    assert_eq!((uniform.quantile(0.0), uniform.quantile(1.0)), (Some(0.0), Some(n - 1.0)));
+    assert_eq!(TDigest::default().quantile(0.5), None);
+}
+
+/// Digests of parts of the values merge to one about as accurate as a digest of them all
+#[test]
+fn merged_parts() {
+    let values = scattered(104_729);
+    let mut merged = TDigest::default();
+    for part in values.chunks(values.len() / 7) {
+        let mut digest = TDigest::new(100.0);
+        part.iter().for_each(|&v| digest.add(v));
+        merged.merge(&digest);
+    }
+    assert_eq!(merged.count(), N as f64);
+    assert!(worst_rank_error(&merged, |x| x / N as f64) < 0.002);
+}
+
+/// A table's samples carry their digests through ingest, parallel or not
+#[test]
+fn sample_quantiles() {
+    let rows: String = scattered(1_299_709).iter().map(|&v| format!("Uniform;{}.{}\n", v as u32 / 10, v as u32 % 10)).collect();
+    for table in [onebrc::produce_table_bytes(rows.as_bytes()), onebrc::produce_table_parallel(rows.as_bytes(), 4)] {
+        let median = table[b"Uniform".as_slice()].quantile(0.5).unwrap();
+        assert!((median - 5000.0).abs() < 10.0, "{median}");
+    }
+    assert_eq!(onebrc::Sample::new(1.0, 2.0, 3.0, 2).quantile(0.5), None);
+}
@@ -0,0 +1,21 @@
+#![cfg(feature = "zstd")]
+
+use std::path::Path;
+
+use onebrc::RunOptions;
+
+/// `tests/data/fixture.txt.zst` is `gen --fixture` compressed with `zstd -19`; reading it through
+/// zstd gives the fixture's expected report.
+#[test]
+fn compressed_fixture_matches_expected_report() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/fixture.txt.zst");
+    let (table, stats) = onebrc::run_zstd(path, &RunOptions::default()).unwrap();
+    assert_eq!(onebrc::report_to_string(&table), gen::FIXTURE_REPORT);
+    assert_eq!(stats.bad_lines, 0);
+}
+
+#[test]
+fn uncompressed_input_fails() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+    assert!(onebrc::run_zstd(path, &RunOptions::default()).is_err());
+}